@@ -0,0 +1,105 @@
+use crate::*;
+
+/// english auction settings and the current highest bid for a sale.
+/// Auctions are priced in a single currency, whose price is used as the reserve price
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Auction {
+    //amount a new bid must add on top of the current highest bid
+    pub min_bid_increment: U128,
+    //block timestamp (in nanoseconds) after which the auction can be settled
+    pub ends_at: U64,
+    //current highest bid, escrowed on the market contract
+    pub highest_bid: Option<Bid>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Bid {
+    pub bidder_id: AccountId,
+    pub amount: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    //settles an auction once it has ended. The highest bidder's escrow pays for the token
+    // through the regular purchase flow. Auctions without bids are simply removed.
+    pub fn settle_auction(&mut self, nft_contract_id: AccountId, token_id: String) {
+        self.assert_buying_not_paused();
+
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        let auction = sale.auction.expect("Sale is not an auction");
+
+        require!(
+            env::block_timestamp() >= auction.ends_at.0,
+            "Auction has not ended yet"
+        );
+
+        match auction.highest_bid {
+            Some(Bid { bidder_id, amount }) => {
+                let (ft_contract_id, _) = sale.sale_conditions.single_price();
+                let ft_contract_id = ft_contract_option(ft_contract_id);
+
+                self.process_purchase(nft_contract_id, token_id, amount, bidder_id, ft_contract_id);
+            }
+            None => {
+                let sale = self.delete_sale(nft_contract_id, token_id);
+                EventLogVariant::Delist(vec![DelistLog::from(&sale)]).emit();
+            }
+        }
+    }
+}
+
+impl Sale {
+    /// whether the sale is an auction that already holds an escrowed bid
+    pub(crate) fn has_bids(&self) -> bool {
+        self.auction
+            .as_ref()
+            .map(|auction| auction.highest_bid.is_some())
+            .unwrap_or(false)
+    }
+}
+
+impl Contract {
+    /// escrows a new bid on an auction and refunds the bid it replaces
+    pub(crate) fn place_bid(
+        &mut self,
+        mut sale: Sale,
+        bidder_id: AccountId,
+        amount: U128,
+        ft_contract_id: Option<FungibleTokenId>,
+    ) {
+        let auction = sale.auction.as_mut().expect("Sale is not an auction");
+
+        require!(
+            env::block_timestamp() < auction.ends_at.0,
+            "Auction has ended"
+        );
+
+        let min_bid = match &auction.highest_bid {
+            Some(bid) => bid
+                .amount
+                .0
+                .checked_add(auction.min_bid_increment.0)
+                .unwrap_or_else(|| env::panic_str("Bid Overflow")),
+            None => {
+                let (_, reserve_price) = sale.sale_conditions.single_price();
+                reserve_price.0
+            }
+        };
+        require!(
+            amount.0 >= min_bid,
+            format!("Bid must be greater than or equal to {}", min_bid)
+        );
+
+        let previous_bid = auction.highest_bid.replace(Bid { bidder_id, amount });
+        if let Some(Bid { bidder_id, amount }) = previous_bid {
+            self.refund_deposit(bidder_id, amount, ft_contract_id, "Outbid Refund");
+        }
+
+        let contract_and_token_id =
+            format!("{}{}{}", sale.nft_contract_id, DELIMETER, sale.token_id);
+        self.sales.insert(&contract_and_token_id, &sale);
+    }
+}
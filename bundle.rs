@@ -0,0 +1,635 @@
+use crate::*;
+use near_sdk::PromiseResult;
+
+//most tokens a bundle can hold, limited by the gas needed to settle them in one purchase
+pub const MAX_BUNDLE_SIZE: usize = 3;
+//gas resolve_bundle_purchase needs besides the token and payout transfers, for checking the
+// payouts, updating the market state and logging
+const GAS_FOR_RESOLVE_BUNDLE_BASE: Gas = Gas(20_000_000_000_000);
+//time (in nanoseconds) after which a bundle purchase that never resolved can be recovered
+const BUNDLE_RECOVERY_DELAY: u64 = 10 * 60 * 1_000_000_000;
+
+/// a token that is part of a bundle
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BundleToken {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    //market's approval ID for the token, set once the owner approves it for the bundle
+    pub approval_id: Option<u64>,
+}
+
+/// several tokens, possibly from different nft contracts, sold together as one lot
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Bundle {
+    pub bundle_id: U64,
+    pub owner_id: AccountId,
+    pub tokens: Vec<BundleToken>,
+    //price of the whole bundle in each fungible token it can be bought with ("near" for NEAR)
+    pub prices: SaleConditions,
+}
+
+impl Bundle {
+    /// whether every token of the bundle was approved, so the bundle can be bought
+    pub(crate) fn is_approved(&self) -> bool {
+        self.tokens.iter().all(|token| token.approval_id.is_some())
+    }
+}
+
+//msg used to approve a token for a bundle (with nft_approve) or buy a bundle in fungible
+// tokens (with ft_transfer_call)
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BundleArgs {
+    pub bundle_id: U64,
+}
+
+//a bundle token's part of the purchase price
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BundleTokenShare {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub price: U128,
+    pub protocol_fee: U128,
+}
+
+/// a bundle purchase whose tokens are being escrowed. It's kept until resolve_bundle_purchase
+/// settles it, so a purchase that never resolved can still be recovered. A recovered purchase
+/// stays until its resolve_bundle_purchase runs, which returns tokens escrowed after recovery
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingBundlePurchase {
+    pub seller_id: AccountId,
+    pub buyer_id: AccountId,
+    pub ft_contract_id: Option<FungibleTokenId>,
+    pub shares: Vec<BundleTokenShare>,
+    //block timestamp (in nanoseconds) of the purchase
+    pub started_at: U64,
+    //whether recover_bundle_purchase already refunded the buyer
+    pub recovered: bool,
+}
+
+impl MarketConfig {
+    /// gas to attach to resolve_bundle_purchase for a bundle of `token_count` tokens, enough to
+    /// release every token and pay out their merged payouts
+    pub(crate) fn gas_for_resolve_bundle_purchase(&self, token_count: usize) -> Gas {
+        let legs = token_count.saturating_mul(self.max_len_payout as usize);
+
+        Gas(self
+            .gas_for_nft_transfer
+            .0
+            .saturating_mul(token_count as u64)
+            .saturating_add(self.gas_for_ft_transfer.0.saturating_mul(legs as u64))
+            .saturating_add(gas_for_resolve_payout(legs).0)
+            .saturating_add(GAS_FOR_RESOLVE_BUNDLE_BASE.0))
+    }
+
+    /// gas a bundle purchase reserves for escrowing its tokens and resolve_bundle_purchase
+    pub(crate) fn gas_for_bundle_purchase(&self, token_count: usize) -> Gas {
+        Gas(self
+            .gas_for_nft_transfer
+            .0
+            .saturating_mul(token_count as u64)
+            .saturating_add(self.gas_for_resolve_bundle_purchase(token_count).0))
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    //create a bundle of tokens sold together. Each token then has to be approved with the msg
+    // {"add_to_bundle": {"bundle_id": "<id>"}} before the bundle can be bought
+    #[payable]
+    pub fn create_bundle(
+        &mut self,
+        tokens: Vec<(AccountId, TokenId)>,
+        prices: SaleConditions,
+    ) -> U64 {
+        assert_one_yocto();
+        self.assert_listing_not_paused();
+
+        let owner_id = env::predecessor_account_id();
+        require!(
+            tokens.len() >= 2 && tokens.len() <= MAX_BUNDLE_SIZE,
+            format!(
+                "A bundle must have between 2 and {} tokens",
+                MAX_BUNDLE_SIZE
+            )
+        );
+        for (index, (nft_contract_id, token_id)) in tokens.iter().enumerate() {
+            require!(
+                !tokens[..index].contains(&(nft_contract_id.clone(), token_id.clone())),
+                "A token can only be added to a bundle once"
+            );
+            self.assert_can_list(nft_contract_id, &owner_id);
+        }
+        require!(!prices.is_empty(), "Bundle must have at least one price");
+        for ft_contract_id in prices.keys() {
+            self.assert_approved_ft_token(ft_contract_id);
+        }
+        self.assert_storage_for_new_entry(&owner_id);
+
+        let bundle_id = self.next_bundle_id;
+        self.next_bundle_id += 1;
+
+        let bundle = Bundle {
+            bundle_id: U64(bundle_id),
+            owner_id: owner_id.clone(),
+            tokens: tokens
+                .into_iter()
+                .map(|(nft_contract_id, token_id)| BundleToken {
+                    nft_contract_id,
+                    token_id,
+                    approval_id: None,
+                })
+                .collect(),
+            prices,
+        };
+        self.bundles.insert(&bundle_id, &bundle);
+
+        let mut by_owner_id = self.bundles_by_owner_id.get(&owner_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::BundlesByOwnerIdInner {
+                    account_id_hash: hash_account_id(&owner_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        by_owner_id.insert(&bundle_id);
+        self.bundles_by_owner_id.insert(&owner_id, &by_owner_id);
+
+        for token in &bundle.tokens {
+            let mut by_nft_contract_id = self
+                .bundles_by_nft_contract_id
+                .get(&token.nft_contract_id)
+                .unwrap_or_else(|| {
+                    UnorderedSet::new(
+                        StorageKey::BundlesByNFTContractIdInner {
+                            account_id_hash: hash_account_id(&token.nft_contract_id),
+                        }
+                        .try_to_vec()
+                        .unwrap(),
+                    )
+                });
+            by_nft_contract_id.insert(&bundle_id);
+            self.bundles_by_nft_contract_id
+                .insert(&token.nft_contract_id, &by_nft_contract_id);
+        }
+
+        EventLogVariant::ListBundle(vec![BundleLog::from(&bundle)]).emit();
+
+        U64(bundle_id)
+    }
+
+    //removes a bundle from the market
+    #[payable]
+    pub fn remove_bundle(&mut self, bundle_id: U64) {
+        assert_one_yocto();
+
+        let bundle = self.internal_remove_bundle(bundle_id.0);
+        require!(
+            env::predecessor_account_id() == bundle.owner_id,
+            "Must be bundle owner"
+        );
+
+        EventLogVariant::DelistBundle(vec![BundleLog::from(&bundle)]).emit();
+    }
+
+    //buy a bundle priced in NEAR. The attached deposit must cover the price, anything above
+    // it is refunded
+    #[payable]
+    pub fn offer_bundle(&mut self, bundle_id: U64) {
+        let near_contract_id = AccountId::new_unchecked("near".to_string());
+        let deposit = env::attached_deposit();
+        let buyer_id = env::predecessor_account_id();
+
+        let price = self.internal_bundle_price(bundle_id.0, &near_contract_id, &buyer_id);
+        require!(
+            deposit >= price.0,
+            format!(
+                "Attached deposit must be greater than or equal to the current price: {:?}",
+                price
+            )
+        );
+
+        let excess = deposit - price.0;
+        if excess > 0 {
+            Promise::new(buyer_id.clone()).transfer(excess);
+        }
+
+        self.internal_purchase_bundle(bundle_id.0, price, buyer_id, None);
+    }
+
+    /*
+        Check the payout of every bundle token. If all tokens made it into the market's
+        escrow with a valid payout, hand them to the buyer and pay the accounts. Otherwise
+        send the escrowed tokens back to the seller and refund the buyer the whole price.
+    */
+    #[private]
+    pub fn resolve_bundle_purchase(&mut self, bundle_id: U64) -> U128 {
+        let purchase = self
+            .pending_bundle_purchases
+            .remove(&bundle_id.0)
+            .expect("No pending bundle purchase");
+
+        let results: Vec<PromiseResult> = (0..purchase.shares.len() as u64)
+            .map(env::promise_result)
+            .collect();
+
+        //the buyer was already refunded, only tokens escrowed after the recovery go back
+        if purchase.recovered {
+            for (share, result) in purchase.shares.iter().zip(results) {
+                if let PromiseResult::Successful(_) = result {
+                    self.release_escrowed_token(
+                        share.nft_contract_id.clone(),
+                        share.token_id.clone(),
+                        purchase.seller_id.clone(),
+                        "Marketplace Bundle Refund",
+                    );
+                }
+            }
+            return U128(0);
+        }
+        let payouts: Vec<Option<HashMap<AccountId, U128>>> = results
+            .iter()
+            .zip(purchase.shares.iter())
+            .map(|(result, share)| match result {
+                PromiseResult::Successful(value) => {
+                    self.valid_payout(value, share.price.0 - share.protocol_fee.0)
+                }
+                _ => None,
+            })
+            .collect();
+
+        if payouts.iter().any(Option::is_none) {
+            let escrowed = results
+                .iter()
+                .map(|result| matches!(result, PromiseResult::Successful(_)))
+                .collect();
+            return self.internal_revert_bundle_purchase(&purchase, escrowed);
+        }
+
+        let PendingBundlePurchase {
+            seller_id,
+            buyer_id,
+            ft_contract_id,
+            shares,
+            ..
+        } = purchase;
+        let price = U128(shares.iter().map(|share| share.price.0).sum());
+
+        let mut payout: HashMap<AccountId, U128> = HashMap::new();
+        let mut purchase_logs = vec![];
+        let mut royalty_logs = vec![];
+
+        for (share, token_payout) in shares.into_iter().zip(payouts.into_iter().flatten()) {
+            self.release_escrowed_token(
+                share.nft_contract_id.clone(),
+                share.token_id.clone(),
+                buyer_id.clone(),
+                "Marketplace Bundle",
+            );
+
+            self.internal_accrue_fee(&ft_contract_id, share.protocol_fee);
+            self.internal_record_trade(Trade {
+                nft_contract_id: share.nft_contract_id.clone(),
+                token_id: share.token_id.clone(),
+                seller_id: seller_id.clone(),
+                buyer_id: buyer_id.clone(),
+                ft_contract_id: ft_contract_id_or_near(&ft_contract_id),
+                price: share.price,
+                timestamp: U64(env::block_timestamp()),
+            });
+            self.internal_record_sale_stats(
+                &share.nft_contract_id,
+                ft_contract_id_or_near(&ft_contract_id),
+                share.price,
+            );
+
+            for (receiver_id, amount) in token_payout {
+                royalty_logs.push(RoyaltyPayoutLog {
+                    receiver_id: receiver_id.clone(),
+                    nft_contract_id: share.nft_contract_id.clone(),
+                    token_id: share.token_id.clone(),
+                    ft_contract_id: ft_contract_id_or_near(&ft_contract_id),
+                    amount,
+                });
+
+                //accounts paid for several tokens get a single transfer
+                let total = payout.entry(receiver_id).or_insert(U128(0));
+                total.0 += amount.0;
+            }
+
+            purchase_logs.push(PurchaseLog {
+                seller_id: seller_id.clone(),
+                buyer_id: buyer_id.clone(),
+                nft_contract_id: share.nft_contract_id,
+                token_id: share.token_id,
+                ft_contract_id: ft_contract_id_or_near(&ft_contract_id),
+                price: share.price,
+                protocol_fee: share.protocol_fee,
+            });
+        }
+
+        EventLogVariant::Purchase(purchase_logs).emit();
+        EventLogVariant::RoyaltyPayout(royalty_logs).emit();
+
+        self.distribute_payout(payout, ft_contract_id, "Marketplace Royalties");
+        price
+    }
+
+    //settle a bundle purchase that never resolved by sending every token the market may hold in
+    // escrow back to the seller and refunding the buyer the whole price. Can be called by
+    // anyone once BUNDLE_RECOVERY_DELAY has passed since the purchase. Tokens escrowed after the
+    // recovery are sent back once resolve_bundle_purchase runs. Returns the refunded price
+    pub fn recover_bundle_purchase(&mut self, bundle_id: U64) -> U128 {
+        let mut purchase = self
+            .pending_bundle_purchases
+            .get(&bundle_id.0)
+            .expect("No pending bundle purchase");
+        require!(!purchase.recovered, "Bundle purchase was already recovered");
+        require!(
+            env::block_timestamp() >= purchase.started_at.0 + BUNDLE_RECOVERY_DELAY,
+            "Bundle purchase is still being resolved"
+        );
+        purchase.recovered = true;
+        self.pending_bundle_purchases
+            .insert(&bundle_id.0, &purchase);
+
+        //tokens that never left the seller can't be transferred back, those transfers just fail
+        let escrowed = vec![true; purchase.shares.len()];
+        self.internal_revert_bundle_purchase(&purchase, escrowed)
+    }
+
+    // views
+
+    //get a bundle purchase that is waiting for resolve_bundle_purchase
+    pub fn get_pending_bundle_purchase(&self, bundle_id: U64) -> Option<PendingBundlePurchase> {
+        self.pending_bundle_purchases.get(&bundle_id.0)
+    }
+
+    //get a bundle by its ID
+    pub fn get_bundle(&self, bundle_id: U64) -> Option<Bundle> {
+        self.bundles.get(&bundle_id.0)
+    }
+
+    //returns paginated bundles created by a given account
+    pub fn get_bundles_by_owner_id(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Bundle> {
+        let bundles = if let Some(bundles) = self.bundles_by_owner_id.get(&account_id) {
+            bundles
+        } else {
+            return vec![];
+        };
+
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        bundles
+            .as_vector()
+            .iter()
+            .skip(start as usize)
+            .take(limit.unwrap_or(0) as usize)
+            .map(|bundle_id| self.bundles.get(&bundle_id).unwrap())
+            .collect()
+    }
+}
+
+impl Contract {
+    /// records the market's approval for a bundle token, called from nft_on_approve
+    pub(crate) fn internal_approve_bundle_token(
+        &mut self,
+        bundle_id: u64,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        owner_id: AccountId,
+        approval_id: u64,
+    ) {
+        let mut bundle = self.bundles.get(&bundle_id).expect("No bundle");
+        require!(bundle.owner_id == owner_id, "Must be bundle owner");
+
+        let token = bundle
+            .tokens
+            .iter_mut()
+            .find(|token| token.nft_contract_id == nft_contract_id && token.token_id == token_id)
+            .expect("Token is not part of the bundle");
+        token.approval_id = Some(approval_id);
+
+        self.bundles.insert(&bundle_id, &bundle);
+    }
+
+    /// removes a bundle from the market and its owner's index. Returns the removed bundle
+    pub(crate) fn internal_remove_bundle(&mut self, bundle_id: u64) -> Bundle {
+        let bundle = self.bundles.remove(&bundle_id).expect("No bundle");
+
+        let mut by_owner_id = self
+            .bundles_by_owner_id
+            .get(&bundle.owner_id)
+            .expect("No bundle by owner_id");
+        by_owner_id.remove(&bundle_id);
+
+        if by_owner_id.is_empty() {
+            self.bundles_by_owner_id.remove(&bundle.owner_id);
+        } else {
+            self.bundles_by_owner_id
+                .insert(&bundle.owner_id, &by_owner_id);
+        }
+
+        for token in &bundle.tokens {
+            //the set is already gone if the bundle holds several tokens of the contract
+            if let Some(mut by_nft_contract_id) =
+                self.bundles_by_nft_contract_id.get(&token.nft_contract_id)
+            {
+                by_nft_contract_id.remove(&bundle_id);
+
+                if by_nft_contract_id.is_empty() {
+                    self.bundles_by_nft_contract_id
+                        .remove(&token.nft_contract_id);
+                } else {
+                    self.bundles_by_nft_contract_id
+                        .insert(&token.nft_contract_id, &by_nft_contract_id);
+                }
+            }
+        }
+
+        bundle
+    }
+
+    /// removes bundles from the market on behalf of the market rather than their owners.
+    /// Returns the number of removed bundles
+    pub(crate) fn internal_delist_bundles(&mut self, bundle_ids: Vec<u64>) -> u64 {
+        let delisted: Vec<Bundle> = bundle_ids
+            .into_iter()
+            .map(|bundle_id| self.internal_remove_bundle(bundle_id))
+            .collect();
+
+        if !delisted.is_empty() {
+            EventLogVariant::DelistBundle(delisted.iter().map(BundleLog::from).collect()).emit();
+        }
+
+        delisted.len() as u64
+    }
+
+    /// the price of a bundle in the given currency, panicking if the buyer can't buy it
+    pub(crate) fn internal_bundle_price(
+        &self,
+        bundle_id: u64,
+        ft_contract_id: &FungibleTokenId,
+        buyer_id: &AccountId,
+    ) -> U128 {
+        self.assert_buying_not_paused();
+
+        let bundle = self.bundles.get(&bundle_id).expect("No bundle");
+        let price = *bundle.prices.get(ft_contract_id).unwrap_or_else(|| {
+            env::panic_str(&format!("Cannot Purchase with {} tokens", ft_contract_id))
+        });
+        self.assert_approved_ft_token(ft_contract_id);
+
+        require!(
+            bundle.is_approved(),
+            "Not every token of the bundle is approved"
+        );
+        require!(bundle.owner_id != *buyer_id, "Cannot buy your own bundle");
+        //a contract or seller blocked after the bundle was created can't be traded anymore
+        for token in &bundle.tokens {
+            self.assert_can_list(&token.nft_contract_id, &bundle.owner_id);
+        }
+
+        price
+    }
+
+    /// removes the bundle and transfers every token into the market's escrow with its payout.
+    /// The price is split evenly over the tokens, with the rounding going to the first one
+    pub(crate) fn internal_purchase_bundle(
+        &mut self,
+        bundle_id: u64,
+        price: U128,
+        buyer_id: AccountId,
+        ft_contract_id: Option<FungibleTokenId>,
+    ) -> Promise {
+        let bundle = self.internal_remove_bundle(bundle_id);
+        let token_count = bundle.tokens.len();
+
+        let purchase_gas = self.config.gas_for_bundle_purchase(token_count);
+        require!(
+            env::prepaid_gas() - env::used_gas() >= purchase_gas,
+            format!(
+                "Attached gas can't cover the {} needed to buy a bundle of {} tokens",
+                purchase_gas.0, token_count
+            )
+        );
+
+        let mut shares = vec![];
+        let mut transfers: Option<Promise> = None;
+
+        for (index, token) in bundle.tokens.into_iter().enumerate() {
+            //a bundled token that is also listed on its own can't stay listed once it's sold
+            let contract_and_token_id =
+                format!("{}{}{}", token.nft_contract_id, DELIMETER, token.token_id);
+            if let Some(sale) = self.sales.get(&contract_and_token_id) {
+                require!(
+                    !sale.has_bids(),
+                    "Cannot sell a bundle token that has auction bids"
+                );
+                self.delete_sale(token.nft_contract_id.clone(), token.token_id.clone());
+                EventLogVariant::Delist(vec![DelistLog::from(&sale)]).emit();
+            }
+
+            let mut share = price.0 / token_count as u128;
+            if index == 0 {
+                share += price.0 % token_count as u128;
+            }
+            let protocol_fee = self.protocol_fee_for(U128(share));
+
+            let transfer = ext_contract::ext(token.nft_contract_id.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(self.config.gas_for_nft_transfer)
+                .nft_transfer_payout(
+                    env::current_account_id(),
+                    token.token_id.clone(),
+                    token.approval_id.unwrap(),
+                    "payout from market".to_string(),
+                    U128(share - protocol_fee.0),
+                    self.config.max_len_payout,
+                );
+            transfers = Some(match transfers {
+                Some(transfers) => transfers.and(transfer),
+                None => transfer,
+            });
+
+            shares.push(BundleTokenShare {
+                nft_contract_id: token.nft_contract_id,
+                token_id: token.token_id,
+                price: U128(share),
+                protocol_fee,
+            });
+        }
+
+        self.pending_bundle_purchases.insert(
+            &bundle_id,
+            &PendingBundlePurchase {
+                seller_id: bundle.owner_id,
+                buyer_id,
+                ft_contract_id,
+                shares,
+                started_at: U64(env::block_timestamp()),
+                recovered: false,
+            },
+        );
+
+        transfers.unwrap().then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(self.config.gas_for_resolve_bundle_purchase(token_count))
+                .resolve_bundle_purchase(U64(bundle_id)),
+        )
+    }
+
+    /// sends the tokens of a failed bundle purchase that made it into escrow back to the seller
+    /// and refunds the buyer the whole price. Returns the refunded price
+    fn internal_revert_bundle_purchase(
+        &self,
+        purchase: &PendingBundlePurchase,
+        escrowed: Vec<bool>,
+    ) -> U128 {
+        let price = U128(purchase.shares.iter().map(|share| share.price.0).sum());
+
+        for (share, escrowed) in purchase.shares.iter().zip(escrowed) {
+            if escrowed {
+                self.release_escrowed_token(
+                    share.nft_contract_id.clone(),
+                    share.token_id.clone(),
+                    purchase.seller_id.clone(),
+                    "Marketplace Bundle Refund",
+                );
+            }
+        }
+
+        EventLogVariant::PurchaseRefund(
+            purchase
+                .shares
+                .iter()
+                .map(|share| PurchaseRefundLog {
+                    buyer_id: purchase.buyer_id.clone(),
+                    nft_contract_id: share.nft_contract_id.clone(),
+                    token_id: share.token_id.clone(),
+                    ft_contract_id: ft_contract_id_or_near(&purchase.ft_contract_id),
+                    price: share.price,
+                })
+                .collect(),
+        )
+        .emit();
+
+        self.refund_deposit(
+            purchase.buyer_id.clone(),
+            price,
+            purchase.ft_contract_id.clone(),
+            "Marketplace Refund",
+        );
+        price
+    }
+}
@@ -0,0 +1,98 @@
+use crate::*;
+use near_sdk::PromiseResult;
+
+//gas resolve_payout needs besides checking the transfers
+const GAS_FOR_RESOLVE_PAYOUT_BASE: Gas = Gas(5_000_000_000_000);
+//gas resolve_payout needs for every transfer it checks, enough to credit a failed one
+const GAS_FOR_RESOLVE_PAYOUT_LEG: Gas = Gas(1_000_000_000_000);
+
+/// gas to attach to resolve_payout for a payout over `legs` accounts
+pub(crate) fn gas_for_resolve_payout(legs: usize) -> Gas {
+    Gas(GAS_FOR_RESOLVE_PAYOUT_LEG
+        .0
+        .saturating_mul(legs as u64)
+        .saturating_add(GAS_FOR_RESOLVE_PAYOUT_BASE.0))
+}
+
+#[near_bindgen]
+impl Contract {
+    //send the caller everything credited to them in the given token ("near" for NEAR) after
+    // payout transfers to them failed. If the claim fails too, it's credited back
+    #[payable]
+    pub fn claim(&mut self, ft_contract_id: FungibleTokenId) -> U128 {
+        assert_one_yocto();
+
+        let account_id = env::predecessor_account_id();
+        let amount = self
+            .claimable
+            .remove(&(account_id.clone(), ft_contract_id.clone()))
+            .unwrap_or(0);
+        require!(amount > 0, "Nothing to claim");
+
+        self.distribute_payout(
+            HashMap::from([(account_id, U128(amount))]),
+            ft_contract_option(ft_contract_id),
+            "Marketplace Claim",
+        );
+
+        U128(amount)
+    }
+
+    /*
+        Check the result of every transfer of a payout, in the order they were joined, and
+        credit each failed amount to its receiver's claimable balance.
+    */
+    #[private]
+    pub fn resolve_payout(
+        &mut self,
+        ft_contract_id: FungibleTokenId,
+        payout: Vec<(AccountId, U128)>,
+    ) -> U128 {
+        let mut credited = 0;
+
+        for (index, (receiver_id, amount)) in payout.into_iter().enumerate() {
+            if let PromiseResult::Successful(_) = env::promise_result(index as u64) {
+                continue;
+            }
+
+            env::log_str(&format!(
+                "Transfer of {} to {} failed, it can be claimed",
+                amount.0, receiver_id
+            ));
+            self.internal_credit_claimable(&receiver_id, &ft_contract_id, amount.0);
+            credited += amount.0;
+        }
+
+        U128(credited)
+    }
+
+    // views
+
+    //get what an account can claim in the given token ("near" for NEAR)
+    pub fn get_claimable(&self, account_id: AccountId, ft_contract_id: FungibleTokenId) -> U128 {
+        U128(
+            self.claimable
+                .get(&(account_id, ft_contract_id))
+                .unwrap_or(0),
+        )
+    }
+}
+
+impl Contract {
+    /// adds a failed payout transfer to the receiver's claimable balance
+    fn internal_credit_claimable(
+        &mut self,
+        account_id: &AccountId,
+        ft_contract_id: &FungibleTokenId,
+        amount: Balance,
+    ) {
+        let key = (account_id.clone(), ft_contract_id.clone());
+        let balance = self
+            .claimable
+            .get(&key)
+            .unwrap_or(0)
+            .checked_add(amount)
+            .unwrap_or_else(|| env::panic_str("Balance Overflow"));
+        self.claimable.insert(&key, &balance);
+    }
+}
@@ -0,0 +1,281 @@
+use crate::*;
+
+/// an escrowed offer to buy any token(s) of an nft contract at a fixed price per token
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionOffer {
+    pub collection_offer_id: U64,
+    //account that made the offer and whose funds are escrowed
+    pub buyer_id: AccountId,
+    //nft contract any token of which can fill the offer
+    pub nft_contract_id: AccountId,
+    //token the offer is paid in ("near" for NEAR)
+    pub ft_contract_id: FungibleTokenId,
+    //amount paid out for each token
+    pub price: SalePriceInTokens,
+    //number of tokens that can still be sold into the offer
+    pub quantity: u32,
+    //block timestamp (in nanoseconds) after which the offer can no longer be filled
+    pub expires_at: Option<U64>,
+}
+
+impl CollectionOffer {
+    pub(crate) fn is_expired(&self) -> bool {
+        self.expires_at
+            .map(|expires_at| env::block_timestamp() >= expires_at.0)
+            .unwrap_or(false)
+    }
+
+    //amount currently escrowed for the tokens that haven't been filled yet
+    pub(crate) fn escrowed_amount(&self) -> U128 {
+        U128(self.price.0 * u128::from(self.quantity))
+    }
+}
+
+//msg used with ft_transfer_call to make a collection offer in fungible tokens
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionOfferArgs {
+    pub nft_contract_id: AccountId,
+    pub price: SalePriceInTokens,
+    pub quantity: u32,
+    pub expires_at: Option<U64>,
+}
+
+#[near_bindgen]
+impl Contract {
+    //make an escrowed offer in NEAR for `quantity` tokens of a collection.
+    // The attached deposit must be exactly price * quantity
+    #[payable]
+    pub fn make_collection_offer(
+        &mut self,
+        nft_contract_id: AccountId,
+        price: U128,
+        quantity: u32,
+        expires_at: Option<U64>,
+    ) -> U64 {
+        let near_contract_id = AccountId::new_unchecked("near".to_string());
+
+        self.internal_add_collection_offer(
+            env::predecessor_account_id(),
+            CollectionOfferArgs {
+                nft_contract_id,
+                price,
+                quantity,
+                expires_at,
+            },
+            near_contract_id,
+            U128(env::attached_deposit()),
+        )
+    }
+
+    //cancel a collection offer and refund the remaining escrow to the buyer.
+    // Expired offers can be cleaned up by anyone
+    #[payable]
+    pub fn cancel_collection_offer(&mut self, collection_offer_id: U64) {
+        assert_one_yocto();
+
+        let collection_offer = self.internal_remove_collection_offer(collection_offer_id.0);
+        require!(
+            env::predecessor_account_id() == collection_offer.buyer_id
+                || collection_offer.is_expired(),
+            "Only the buyer can cancel an active offer"
+        );
+
+        self.refund_deposit(
+            collection_offer.buyer_id.clone(),
+            collection_offer.escrowed_amount(),
+            ft_contract_option(collection_offer.ft_contract_id),
+            "Offer Refund",
+        );
+    }
+}
+
+impl Contract {
+    /// stores a new collection offer whose funds have already been received by the market
+    pub(crate) fn internal_add_collection_offer(
+        &mut self,
+        buyer_id: AccountId,
+        collection_offer_args: CollectionOfferArgs,
+        ft_contract_id: FungibleTokenId,
+        amount: U128,
+    ) -> U64 {
+        let CollectionOfferArgs {
+            nft_contract_id,
+            price,
+            quantity,
+            expires_at,
+        } = collection_offer_args;
+
+        self.assert_offers_not_paused();
+        require!(
+            self.approved_ft_tokens.contains(&ft_contract_id),
+            "Only Approved Fungible Tokens can be used for offers"
+        );
+        require!(price.0 > 0, "Price must be greater than 0");
+        require!(quantity > 0, "Quantity must be greater than 0");
+        require!(
+            price.0.checked_mul(u128::from(quantity)) == Some(amount.0),
+            "Attached amount must equal price * quantity"
+        );
+        if let Some(expires_at) = expires_at {
+            require!(
+                expires_at.0 > env::block_timestamp(),
+                "Offer must expire in the future"
+            );
+        }
+        self.assert_storage_for_new_entry(&buyer_id);
+
+        let collection_offer_id = self.next_offer_id;
+        self.next_offer_id += 1;
+
+        self.collection_offers.insert(
+            &collection_offer_id,
+            &CollectionOffer {
+                collection_offer_id: U64(collection_offer_id),
+                buyer_id: buyer_id.clone(),
+                nft_contract_id: nft_contract_id.clone(),
+                ft_contract_id,
+                price,
+                quantity,
+                expires_at,
+            },
+        );
+
+        let mut by_nft_contract_id = self
+            .collection_offers_by_nft_contract_id
+            .get(&nft_contract_id)
+            .unwrap_or_else(|| {
+                UnorderedSet::new(
+                    StorageKey::CollectionOffersByNFTContractIdInner {
+                        account_id_hash: hash_account_id(&nft_contract_id),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                )
+            });
+        by_nft_contract_id.insert(&collection_offer_id);
+        self.collection_offers_by_nft_contract_id
+            .insert(&nft_contract_id, &by_nft_contract_id);
+
+        let mut by_buyer_id = self
+            .collection_offers_by_buyer_id
+            .get(&buyer_id)
+            .unwrap_or_else(|| {
+                UnorderedSet::new(
+                    StorageKey::CollectionOffersByBuyerIdInner {
+                        account_id_hash: hash_account_id(&buyer_id),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                )
+            });
+        by_buyer_id.insert(&collection_offer_id);
+        self.collection_offers_by_buyer_id
+            .insert(&buyer_id, &by_buyer_id);
+
+        U64(collection_offer_id)
+    }
+
+    /// removes a collection offer from the market and its indexes. Returns the removed offer
+    pub(crate) fn internal_remove_collection_offer(
+        &mut self,
+        collection_offer_id: u64,
+    ) -> CollectionOffer {
+        let collection_offer = self
+            .collection_offers
+            .remove(&collection_offer_id)
+            .expect("No collection offer");
+
+        let mut by_nft_contract_id = self
+            .collection_offers_by_nft_contract_id
+            .get(&collection_offer.nft_contract_id)
+            .expect("No collection offer by nft_contract_id");
+        by_nft_contract_id.remove(&collection_offer_id);
+
+        if by_nft_contract_id.is_empty() {
+            self.collection_offers_by_nft_contract_id
+                .remove(&collection_offer.nft_contract_id);
+        } else {
+            self.collection_offers_by_nft_contract_id
+                .insert(&collection_offer.nft_contract_id, &by_nft_contract_id);
+        }
+
+        let mut by_buyer_id = self
+            .collection_offers_by_buyer_id
+            .get(&collection_offer.buyer_id)
+            .expect("No collection offer by buyer_id");
+        by_buyer_id.remove(&collection_offer_id);
+
+        if by_buyer_id.is_empty() {
+            self.collection_offers_by_buyer_id
+                .remove(&collection_offer.buyer_id);
+        } else {
+            self.collection_offers_by_buyer_id
+                .insert(&collection_offer.buyer_id, &by_buyer_id);
+        }
+
+        collection_offer
+    }
+
+    /// sells a token into a collection offer on behalf of its owner, who approved the market in
+    /// nft_on_approve. One unit of the offer is used up and its escrow goes through the regular
+    /// payout flow
+    pub(crate) fn fill_collection_offer(
+        &mut self,
+        collection_offer_id: u64,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        owner_id: AccountId,
+        approval_id: u64,
+    ) -> Promise {
+        self.assert_offers_not_paused();
+
+        let mut collection_offer = self
+            .collection_offers
+            .get(&collection_offer_id)
+            .expect("No collection offer");
+
+        require!(
+            collection_offer.nft_contract_id == nft_contract_id,
+            "Collection offer was made on a different nft contract"
+        );
+        require!(!collection_offer.is_expired(), "Offer has expired");
+        require!(
+            collection_offer.buyer_id != owner_id,
+            "Cannot accept your own offer"
+        );
+
+        collection_offer.quantity -= 1;
+        if collection_offer.quantity == 0 {
+            self.internal_remove_collection_offer(collection_offer_id);
+        } else {
+            self.collection_offers
+                .insert(&collection_offer_id, &collection_offer);
+        }
+
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        if let Some(sale) = self.sales.get(&contract_and_token_id) {
+            require!(
+                !sale.has_bids(),
+                "Cannot accept an offer on an auction that has bids"
+            );
+            self.delete_sale(nft_contract_id.clone(), token_id.clone());
+            EventLogVariant::Delist(vec![DelistLog::from(&sale)]).emit();
+        }
+
+        let sold_token = SoldToken {
+            nft_contract_id,
+            token_id,
+            seller_id: owner_id,
+        };
+
+        self.transfer_with_payout(
+            sold_token,
+            approval_id,
+            collection_offer.price,
+            collection_offer.buyer_id,
+            ft_contract_option(collection_offer.ft_contract_id),
+        )
+    }
+}
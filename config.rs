@@ -0,0 +1,102 @@
+use crate::*;
+
+//gas resolve_purchase needs besides the royalty transfers, for checking the payout, updating
+// the market state and logging
+pub const GAS_FOR_RESOLVE_PURCHASE_BASE: Gas = Gas(20_000_000_000_000);
+//most gas a purchase, swap or bundle purchase can reserve for its transfers and callback,
+// leaving the rest of the 300 Tgas limit to the method starting it
+pub const MAX_GAS_FOR_PURCHASE: Gas = Gas(250_000_000_000_000);
+
+/// payout limit and gas budgets of the purchase flow, set by the owner
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MarketConfig {
+    //most accounts a token's payout can be split over, passed to nft_transfer_payout
+    pub max_len_payout: u32,
+    //gas attached to nft_transfer and nft_transfer_payout calls
+    pub gas_for_nft_transfer: Gas,
+    //gas attached to every ft_transfer call, including each royalty transfer
+    pub gas_for_ft_transfer: Gas,
+    //gas attached to resolve_purchase
+    pub gas_for_resolve_purchase: Gas,
+}
+
+impl Default for MarketConfig {
+    fn default() -> Self {
+        Self {
+            max_len_payout: 7,
+            gas_for_nft_transfer: Gas(15_000_000_000_000),
+            gas_for_ft_transfer: Gas(5_000_000_000_000),
+            gas_for_resolve_purchase: Gas(115_000_000_000_000),
+        }
+    }
+}
+
+impl MarketConfig {
+    /// gas offer_batch reserves for every purchase: the nft transfer, resolve_purchase and
+    /// starting the purchase
+    pub(crate) fn gas_for_batch_purchase(&self) -> Gas {
+        Gas(self
+            .gas_for_nft_transfer
+            .0
+            .saturating_add(self.gas_for_resolve_purchase.0)
+            .saturating_add(GAS_FOR_SWEEP_ITEM.0))
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    //set the payout limit and gas budgets. resolve_purchase has to be given enough gas to
+    // send an ft_transfer to every account of the longest allowed payout and check them in
+    // resolve_payout, and a batch purchase, a swap and the purchase of a full bundle each
+    // have to fit in MAX_GAS_FOR_PURCHASE
+    pub fn set_market_config(&mut self, config: MarketConfig) {
+        self.assert_owner();
+        require!(
+            config.max_len_payout > 0,
+            "Max payout length must be at least 1"
+        );
+        require!(
+            config.gas_for_nft_transfer.0 > 0 && config.gas_for_ft_transfer.0 > 0,
+            "Transfer gas cannot be 0"
+        );
+
+        let required_resolve_gas = config
+            .gas_for_ft_transfer
+            .0
+            .saturating_mul(config.max_len_payout as u64)
+            .saturating_add(GAS_FOR_RESOLVE_PURCHASE_BASE.0)
+            .saturating_add(gas_for_resolve_payout(config.max_len_payout as usize).0);
+        require!(
+            config.gas_for_resolve_purchase.0 >= required_resolve_gas,
+            format!(
+                "Resolve purchase gas must be at least {} for a payout of {} accounts",
+                required_resolve_gas, config.max_len_payout
+            )
+        );
+        for (action, gas) in [
+            ("a batch purchase", config.gas_for_batch_purchase()),
+            ("a swap", config.gas_for_swap()),
+            (
+                "a bundle purchase",
+                config.gas_for_bundle_purchase(MAX_BUNDLE_SIZE),
+            ),
+        ] {
+            require!(
+                gas.0 <= MAX_GAS_FOR_PURCHASE.0,
+                format!(
+                    "Gas needed for {} ({}) cannot be more than {}",
+                    action, gas.0, MAX_GAS_FOR_PURCHASE.0
+                )
+            );
+        }
+
+        self.config = config;
+    }
+
+    // views
+
+    pub fn get_market_config(&self) -> MarketConfig {
+        self.config
+    }
+}
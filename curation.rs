@@ -0,0 +1,222 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    //turn curation mode on or off. While it's on, only allow-listed nft contracts can be listed
+    pub fn set_curation_enabled(&mut self, enabled: bool) {
+        self.assert_role(Role::Curator);
+
+        self.curation_enabled = enabled;
+    }
+
+    //add nft contracts to the allow-list used in curation mode
+    pub fn allow_nft_contracts(&mut self, nft_contract_ids: Vec<AccountId>) -> Vec<bool> {
+        self.assert_role(Role::Curator);
+
+        nft_contract_ids
+            .iter()
+            .map(|nft_contract_id| self.allowed_nft_contract_ids.insert(nft_contract_id))
+            .collect()
+    }
+
+    //remove nft contracts from the allow-list. Their existing sales stay listed
+    pub fn disallow_nft_contracts(&mut self, nft_contract_ids: Vec<AccountId>) -> Vec<bool> {
+        self.assert_role(Role::Curator);
+
+        nft_contract_ids
+            .iter()
+            .map(|nft_contract_id| self.allowed_nft_contract_ids.remove(nft_contract_id))
+            .collect()
+    }
+
+    //stop nft contracts from being listed, whether curation mode is on or not. Their existing
+    // sales can then be removed with delist_blocked_nft_contract
+    pub fn block_nft_contracts(&mut self, nft_contract_ids: Vec<AccountId>) -> Vec<bool> {
+        self.assert_role(Role::Curator);
+
+        nft_contract_ids
+            .iter()
+            .map(|nft_contract_id| self.blocked_nft_contract_ids.insert(nft_contract_id))
+            .collect()
+    }
+
+    pub fn unblock_nft_contracts(&mut self, nft_contract_ids: Vec<AccountId>) -> Vec<bool> {
+        self.assert_role(Role::Curator);
+
+        nft_contract_ids
+            .iter()
+            .map(|nft_contract_id| self.blocked_nft_contract_ids.remove(nft_contract_id))
+            .collect()
+    }
+
+    //stop accounts from listing tokens. Their existing sales can then be removed with
+    // delist_blocked_seller
+    pub fn block_sellers(&mut self, seller_ids: Vec<AccountId>) -> Vec<bool> {
+        self.assert_role(Role::Curator);
+
+        seller_ids
+            .iter()
+            .map(|seller_id| self.blocked_seller_ids.insert(seller_id))
+            .collect()
+    }
+
+    pub fn unblock_sellers(&mut self, seller_ids: Vec<AccountId>) -> Vec<bool> {
+        self.assert_role(Role::Curator);
+
+        seller_ids
+            .iter()
+            .map(|seller_id| self.blocked_seller_ids.remove(seller_id))
+            .collect()
+    }
+
+    //remove up to `limit` sales and bundles of a blocked nft contract, refunding any auction
+    // bids. Can be called by anyone. Returns the number of removed sales and bundles
+    pub fn delist_blocked_nft_contract(&mut self, nft_contract_id: AccountId, limit: u64) -> u64 {
+        require!(
+            self.blocked_nft_contract_ids.contains(&nft_contract_id),
+            "NFT contract is not blocked"
+        );
+
+        let token_ids: Vec<TokenId> = self
+            .by_nft_contract_id
+            .get(&nft_contract_id)
+            .map(|token_ids| token_ids.iter().take(limit as usize).collect())
+            .unwrap_or_default();
+
+        let delisted: Vec<Sale> = token_ids
+            .into_iter()
+            .map(|token_id| self.internal_delist_sale(nft_contract_id.clone(), token_id))
+            .collect();
+
+        if !delisted.is_empty() {
+            EventLogVariant::Delist(delisted.iter().map(DelistLog::from).collect()).emit();
+        }
+
+        let bundle_ids: Vec<u64> = self
+            .bundles_by_nft_contract_id
+            .get(&nft_contract_id)
+            .map(|bundle_ids| {
+                bundle_ids
+                    .iter()
+                    .take(limit as usize - delisted.len())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        delisted.len() as u64 + self.internal_delist_bundles(bundle_ids)
+    }
+
+    //remove up to `limit` sales and bundles of a blocked seller, refunding any auction bids.
+    // Can be called by anyone. Returns the number of removed sales and bundles
+    pub fn delist_blocked_seller(&mut self, seller_id: AccountId, limit: u64) -> u64 {
+        require!(
+            self.blocked_seller_ids.contains(&seller_id),
+            "Seller is not blocked"
+        );
+
+        let contract_and_token_ids: Vec<ContractAndTokenId> = self
+            .by_owner_id
+            .get(&seller_id)
+            .map(|sales| sales.iter().take(limit as usize).collect())
+            .unwrap_or_default();
+
+        let bundle_limit = limit as usize - contract_and_token_ids.len();
+        let mut delisted: Vec<Sale> = vec![];
+        for contract_and_token_id in contract_and_token_ids {
+            match self.sales.get(&contract_and_token_id) {
+                Some(sale) if sale.owner_id == seller_id => {
+                    delisted.push(self.internal_delist_sale(
+                        AccountId::new_unchecked(sale.nft_contract_id),
+                        sale.token_id,
+                    ))
+                }
+                //the sale is gone or was listed again by a new owner
+                _ => self.internal_remove_owner_sale(&seller_id, &contract_and_token_id),
+            }
+        }
+
+        if !delisted.is_empty() {
+            EventLogVariant::Delist(delisted.iter().map(DelistLog::from).collect()).emit();
+        }
+
+        let bundle_ids: Vec<u64> = self
+            .bundles_by_owner_id
+            .get(&seller_id)
+            .map(|bundle_ids| bundle_ids.iter().take(bundle_limit).collect())
+            .unwrap_or_default();
+
+        delisted.len() as u64 + self.internal_delist_bundles(bundle_ids)
+    }
+
+    // views
+
+    pub fn get_curation_enabled(&self) -> bool {
+        self.curation_enabled
+    }
+
+    //returns paginated nft contracts that can be listed in curation mode
+    pub fn get_allowed_nft_contracts(
+        &self,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<AccountId> {
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        self.allowed_nft_contract_ids
+            .iter()
+            .skip(start as usize)
+            .take(limit.unwrap_or(0) as usize)
+            .collect()
+    }
+
+    //returns paginated nft contracts that can't be listed
+    pub fn get_blocked_nft_contracts(
+        &self,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<AccountId> {
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        self.blocked_nft_contract_ids
+            .iter()
+            .skip(start as usize)
+            .take(limit.unwrap_or(0) as usize)
+            .collect()
+    }
+
+    //returns paginated accounts that can't list tokens
+    pub fn get_blocked_sellers(
+        &self,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<AccountId> {
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        self.blocked_seller_ids
+            .iter()
+            .skip(start as usize)
+            .take(limit.unwrap_or(0) as usize)
+            .collect()
+    }
+}
+
+impl Contract {
+    /// panics if the seller or nft contract is blocked, or if curation mode is on and the
+    /// nft contract isn't allow-listed
+    pub(crate) fn assert_can_list(&self, nft_contract_id: &AccountId, owner_id: &AccountId) {
+        require!(
+            !self.blocked_nft_contract_ids.contains(nft_contract_id),
+            "NFT contract is blocked"
+        );
+        require!(
+            !self.blocked_seller_ids.contains(owner_id),
+            "Seller is blocked"
+        );
+        if self.curation_enabled {
+            require!(
+                self.allowed_nft_contract_ids.contains(nft_contract_id),
+                "NFT contract is not allowed"
+            );
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use crate::nft_callbacks::SaleConditionArgs;
+use crate::*;
+
+/// how the price steps down between the start and the end of the decay window
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde", tag = "kind", rename_all = "snake_case")]
+pub enum DecayCurve {
+    //price drops continuously every block
+    Linear,
+    //price drops in `steps` equal chunks spread over the window
+    Stepwise { steps: u32 },
+}
+
+/// dutch auction schedule. The price decays from the sale conditions price down to
+/// `floor_price` between `starts_at` and `ends_at` (block timestamps in nanoseconds)
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceDecay {
+    pub floor_price: SalePriceInTokens,
+    pub starts_at: U64,
+    pub ends_at: U64,
+    pub curve: DecayCurve,
+}
+
+impl SaleConditionArgs {
+    /// the price a buyer paying in `ft_contract_id` has to pay at the current block timestamp.
+    /// None if the sale can't be bought with that token
+    pub(crate) fn price_for(&self, ft_contract_id: &FungibleTokenId) -> Option<U128> {
+        let price = *self.prices.get(ft_contract_id)?;
+
+        let decay = if let Some(decay) = &self.decay {
+            decay
+        } else {
+            return Some(price);
+        };
+
+        let now = env::block_timestamp();
+        if now <= decay.starts_at.0 {
+            return Some(price);
+        }
+        if now >= decay.ends_at.0 {
+            return Some(decay.floor_price);
+        }
+
+        let elapsed = u128::from(now - decay.starts_at.0);
+        let duration = u128::from(decay.ends_at.0 - decay.starts_at.0);
+        let total_drop = price.0 - decay.floor_price.0;
+
+        let drop = match decay.curve {
+            DecayCurve::Linear => scale(total_drop, elapsed, duration),
+            DecayCurve::Stepwise { steps } => {
+                let steps = u128::from(steps);
+                let steps_taken = elapsed * steps / duration;
+                scale(total_drop, steps_taken, steps)
+            }
+        };
+
+        Some(U128(price.0 - drop))
+    }
+
+    /// panics if the decay schedule can't produce a valid price curve
+    pub(crate) fn assert_valid_decay(&self) {
+        if let Some(decay) = &self.decay {
+            let (_, price) = self.single_price();
+            require!(
+                decay.floor_price.0 <= price.0,
+                "Floor price must be less than or equal to the start price"
+            );
+            require!(
+                decay.ends_at.0 > decay.starts_at.0,
+                "Decay must end after it starts"
+            );
+            if let DecayCurve::Stepwise { steps } = decay.curve {
+                require!(steps > 0, "Stepwise decay needs at least one step");
+            }
+        }
+    }
+}
+
+//computes amount * numerator / denominator without overflowing for large token amounts
+fn scale(amount: u128, numerator: u128, denominator: u128) -> u128 {
+    amount / denominator * numerator + amount % denominator * numerator / denominator
+}
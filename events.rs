@@ -0,0 +1,231 @@
+use std::fmt;
+
+use crate::*;
+
+/// name and version of the NEP-297 standard the market events follow
+pub const NFT_MARKET_STANDARD_NAME: &str = "nft_market";
+pub const NFT_MARKET_STANDARD_VERSION: &str = "1.4.0";
+
+/// Enum that represents the data type of the EventLog.
+/// The enum can either be a list, delist, price update, price removal, purchase, purchase refund,
+/// royalty payout, non-compliant payout, swap listing, swap, reverted swap, bundle listing or
+/// bundle delisting
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+#[serde(crate = "near_sdk::serde")]
+#[non_exhaustive]
+pub enum EventLogVariant {
+    List(Vec<ListLog>),
+    Delist(Vec<DelistLog>),
+    UpdatePrice(Vec<UpdatePriceLog>),
+    RemovePrice(Vec<RemovePriceLog>),
+    Purchase(Vec<PurchaseLog>),
+    PurchaseRefund(Vec<PurchaseRefundLog>),
+    RoyaltyPayout(Vec<RoyaltyPayoutLog>),
+    NonCompliantPayout(Vec<NonCompliantPayoutLog>),
+    ListSwap(Vec<ListSwapLog>),
+    Swap(Vec<SwapLog>),
+    SwapRevert(Vec<SwapLog>),
+    ListBundle(Vec<BundleLog>),
+    DelistBundle(Vec<BundleLog>),
+}
+
+/// Interface to capture data about an event
+///
+/// Arguments:
+/// * `standard`: name of standard e.g. nft_market
+/// * `version`: e.g. 1.0.0
+/// * `event`: associate event data
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EventLog {
+    pub standard: String,
+    pub version: String,
+
+    // `flatten` to not have "event": {<EventLogVariant>} in the JSON, just have the contents of {<EventLogVariant>}.
+    #[serde(flatten)]
+    pub event: EventLogVariant,
+}
+
+impl fmt::Display for EventLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!(
+            "EVENT_JSON:{}",
+            &near_sdk::serde_json::to_string(self).map_err(|_| fmt::Error)?
+        ))
+    }
+}
+
+impl EventLogVariant {
+    /// logs the event as an `EVENT_JSON:` string under the nft_market standard
+    pub(crate) fn emit(self) {
+        let event_log = EventLog {
+            standard: NFT_MARKET_STANDARD_NAME.to_string(),
+            version: NFT_MARKET_STANDARD_VERSION.to_string(),
+            event: self,
+        };
+
+        env::log_str(&event_log.to_string());
+    }
+}
+
+/// A token was listed on the market
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ListLog {
+    pub owner_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub approval_id: u64,
+    pub ft_contract_id: FungibleTokenId,
+    pub price: U128,
+}
+
+/// A listing was removed from the market without being sold
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DelistLog {
+    pub owner_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+}
+
+impl From<&Sale> for DelistLog {
+    fn from(sale: &Sale) -> Self {
+        Self {
+            owner_id: sale.owner_id.clone(),
+            nft_contract_id: AccountId::new_unchecked(sale.nft_contract_id.clone()),
+            token_id: sale.token_id.clone(),
+        }
+    }
+}
+
+/// The seller changed or added the price of a listing in a currency
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct UpdatePriceLog {
+    pub owner_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub ft_contract_id: FungibleTokenId,
+    pub price: U128,
+}
+
+/// The seller stopped accepting a currency for a listing
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RemovePriceLog {
+    pub owner_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub ft_contract_id: FungibleTokenId,
+}
+
+/// A token was sold and the payment was distributed
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PurchaseLog {
+    pub seller_id: AccountId,
+    pub buyer_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub ft_contract_id: FungibleTokenId,
+    pub price: U128,
+    pub protocol_fee: U128,
+}
+
+/// A purchase failed and the buyer was refunded
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PurchaseRefundLog {
+    pub buyer_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub ft_contract_id: FungibleTokenId,
+    pub price: U128,
+}
+
+/// Part of a sale price was paid to an account of the nft contract's payout
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RoyaltyPayoutLog {
+    pub receiver_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub ft_contract_id: FungibleTokenId,
+    pub amount: U128,
+}
+
+/// An nft contract transferred a sold token but returned an invalid payout, so the seller was
+/// paid the whole payout balance instead
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NonCompliantPayoutLog {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub seller_id: AccountId,
+    pub buyer_id: AccountId,
+    pub ft_contract_id: FungibleTokenId,
+    pub amount: U128,
+}
+
+/// A token was listed in exchange for another token
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ListSwapLog {
+    pub owner_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub approval_id: u64,
+    pub wanted: WantedToken,
+    pub sweetener: Option<Sweetener>,
+}
+
+/// Two tokens were swapped, or a swap was reverted because a token couldn't be escrowed
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SwapLog {
+    pub seller_id: AccountId,
+    pub taker_id: AccountId,
+    pub listed_nft_contract_id: AccountId,
+    pub listed_token_id: TokenId,
+    pub offered_nft_contract_id: AccountId,
+    pub offered_token_id: TokenId,
+    pub sweetener: Option<Sweetener>,
+}
+
+impl From<&PendingSwap> for SwapLog {
+    fn from(swap: &PendingSwap) -> Self {
+        Self {
+            seller_id: swap.seller_id.clone(),
+            taker_id: swap.taker_id.clone(),
+            listed_nft_contract_id: swap.listed_nft_contract_id.clone(),
+            listed_token_id: swap.listed_token_id.clone(),
+            offered_nft_contract_id: swap.offered_nft_contract_id.clone(),
+            offered_token_id: swap.offered_token_id.clone(),
+            sweetener: swap.sweetener.clone(),
+        }
+    }
+}
+
+/// A bundle was listed or removed from the market without being sold
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BundleLog {
+    pub bundle_id: U64,
+    pub owner_id: AccountId,
+    pub tokens: Vec<BundleToken>,
+    pub prices: SaleConditions,
+}
+
+impl From<&Bundle> for BundleLog {
+    fn from(bundle: &Bundle) -> Self {
+        Self {
+            bundle_id: bundle.bundle_id,
+            owner_id: bundle.owner_id.clone(),
+            tokens: bundle.tokens.clone(),
+            prices: bundle.prices.clone(),
+        }
+    }
+}
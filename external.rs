@@ -1,7 +1,8 @@
 use crate::*;
 
 /// Deliver the token to the buyer and give the market a payout object
-/// it can use to allocate money to the right accounts.
+/// it can use to allocate money to the right accounts. Plain nft transfers
+/// move tokens in and out of the market's escrow for swaps.
 #[ext_contract(ext_contract)]
 trait ExtContract {
     fn nft_transfer_payout(
@@ -14,5 +15,13 @@ trait ExtContract {
         max_len_payout: u32,
     );
 
+    fn nft_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+    );
+
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
 }
@@ -0,0 +1,119 @@
+use crate::*;
+use near_sdk::PromiseResult;
+
+//the highest protocol fee the owner can set (10%)
+pub const MAX_PROTOCOL_FEE_BPS: u16 = 1_000;
+const BASIS_POINTS: u128 = 10_000;
+//gas attached to resolve_withdraw_fees, enough to credit the fees back
+const GAS_FOR_RESOLVE_WITHDRAW_FEES: Gas = Gas(5_000_000_000_000);
+
+#[near_bindgen]
+impl Contract {
+    //set the fee (in basis points) the market keeps from every sale
+    pub fn set_protocol_fee(&mut self, fee_bps: u16) {
+        self.assert_role(Role::FeeManager);
+        require!(
+            fee_bps <= MAX_PROTOCOL_FEE_BPS,
+            format!(
+                "Protocol fee cannot be more than {} bps",
+                MAX_PROTOCOL_FEE_BPS
+            )
+        );
+
+        self.protocol_fee_bps = fee_bps;
+    }
+
+    //set the account that accrued protocol fees are withdrawn to
+    pub fn set_fee_treasury(&mut self, fee_treasury_id: AccountId) {
+        self.assert_owner();
+
+        self.fee_treasury_id = fee_treasury_id;
+    }
+
+    //send all fees accrued in the given token ("near" for NEAR) to the fee treasury. If the
+    // transfer fails, the fees are accrued again
+    pub fn withdraw_fees(&mut self, ft_contract_id: FungibleTokenId) -> U128 {
+        self.assert_role(Role::FeeManager);
+
+        let amount = self.accrued_fees.remove(&ft_contract_id).unwrap_or(0);
+        if amount > 0 {
+            let transfer = match ft_contract_option(ft_contract_id.clone()) {
+                Some(ft_contract) => ext_contract::ext(ft_contract)
+                    .with_attached_deposit(1)
+                    .with_static_gas(self.config.gas_for_ft_transfer)
+                    .ft_transfer(
+                        self.fee_treasury_id.clone(),
+                        U128(amount),
+                        Some("Marketplace Fees".to_string()),
+                    ),
+                None => Promise::new(self.fee_treasury_id.clone()).transfer(amount),
+            };
+            transfer.then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_WITHDRAW_FEES)
+                    .resolve_withdraw_fees(ft_contract_id, U128(amount)),
+            );
+        }
+
+        U128(amount)
+    }
+
+    //credit fees back to the accrued fees if their withdrawal failed. Returns the amount
+    // credited back
+    #[private]
+    pub fn resolve_withdraw_fees(&mut self, ft_contract_id: FungibleTokenId, amount: U128) -> U128 {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return U128(0);
+        }
+
+        env::log_str(&format!(
+            "Withdrawal of {} fees in {} failed, they stay accrued",
+            amount.0, ft_contract_id
+        ));
+        self.internal_accrue_fee(&ft_contract_option(ft_contract_id), amount);
+        amount
+    }
+
+    // views
+
+    //returns the protocol fee in basis points
+    pub fn get_protocol_fee(&self) -> u16 {
+        self.protocol_fee_bps
+    }
+
+    //returns the account fees are withdrawn to
+    pub fn get_fee_treasury(&self) -> AccountId {
+        self.fee_treasury_id.clone()
+    }
+
+    //returns the fees accrued in the given token ("near" for NEAR) that haven't been withdrawn yet
+    pub fn get_accrued_fees(&self, ft_contract_id: FungibleTokenId) -> U128 {
+        U128(self.accrued_fees.get(&ft_contract_id).unwrap_or(0))
+    }
+}
+
+impl Contract {
+    /// the part of a sale price the market keeps as protocol fee
+    pub(crate) fn protocol_fee_for(&self, price: U128) -> U128 {
+        U128(
+            price.0 / BASIS_POINTS * u128::from(self.protocol_fee_bps)
+                + price.0 % BASIS_POINTS * u128::from(self.protocol_fee_bps) / BASIS_POINTS,
+        )
+    }
+
+    /// records a protocol fee taken from a settled sale
+    pub(crate) fn internal_accrue_fee(
+        &mut self,
+        ft_contract_id: &Option<FungibleTokenId>,
+        protocol_fee: U128,
+    ) {
+        if protocol_fee.0 == 0 {
+            return;
+        }
+
+        let ft_contract_id = ft_contract_id_or_near(ft_contract_id);
+        let accrued = self.accrued_fees.get(&ft_contract_id).unwrap_or(0);
+        self.accrued_fees
+            .insert(&ft_contract_id, &(accrued + protocol_fee.0));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::*;
+use near_sdk::collections::Vector;
+
+//number of most recent trades kept for each nft contract
+pub const MAX_COLLECTION_TRADES: u64 = 100;
+//number of most recent trades kept for each token
+pub const MAX_TOKEN_TRADES: u64 = 5;
+
+/// a completed purchase, recorded once the payout went through in resolve_purchase
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Trade {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub seller_id: AccountId,
+    pub buyer_id: AccountId,
+    //token the price was paid in ("near" for NEAR)
+    pub ft_contract_id: FungibleTokenId,
+    pub price: U128,
+    //block timestamp (in nanoseconds) of the purchase
+    pub timestamp: U64,
+}
+
+/// ring buffer holding the most recent `capacity` trades. Once full, every new trade
+/// overwrites the oldest one
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TradeHistory {
+    trades: Vector<Trade>,
+    capacity: u64,
+    //number of trades ever recorded, the next one is written at total % capacity
+    total: u64,
+}
+
+impl TradeHistory {
+    fn new(prefix: Vec<u8>, capacity: u64) -> Self {
+        Self {
+            trades: Vector::new(prefix),
+            capacity,
+            total: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.trades.clear();
+        self.total = 0;
+    }
+
+    fn push(&mut self, trade: &Trade) {
+        if self.trades.len() < self.capacity {
+            self.trades.push(trade);
+        } else {
+            self.trades.replace(self.total % self.capacity, trade);
+        }
+        self.total += 1;
+    }
+
+    /// the kept trades, most recent first
+    fn iter_latest(&self) -> impl Iterator<Item = Trade> + '_ {
+        (0..self.trades.len()).map(move |i| {
+            self.trades
+                .get((self.total - 1 - i) % self.capacity)
+                .unwrap()
+        })
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    //drop the trade history of the given tokens (contract + DELIMITER + token ID), freeing its
+    // storage. Returns which tokens had a history
+    pub fn prune_token_trades(
+        &mut self,
+        contract_and_token_ids: Vec<ContractAndTokenId>,
+    ) -> Vec<bool> {
+        self.assert_role(Role::Admin);
+        require!(
+            contract_and_token_ids.len() <= MAX_BATCH_SIZE,
+            format!("Cannot prune more than {} tokens at once", MAX_BATCH_SIZE)
+        );
+
+        contract_and_token_ids
+            .iter()
+            .map(|contract_and_token_id| {
+                if let Some(mut history) = self.token_trades.remove(contract_and_token_id) {
+                    history.clear();
+                    true
+                } else {
+                    false
+                }
+            })
+            .collect()
+    }
+
+    //drop the trade history of an nft contract, freeing its storage. Returns whether it had one
+    pub fn prune_collection_trades(&mut self, nft_contract_id: AccountId) -> bool {
+        self.assert_role(Role::Admin);
+
+        if let Some(mut history) = self.collection_trades.remove(&nft_contract_id) {
+            history.clear();
+            true
+        } else {
+            false
+        }
+    }
+
+    // views
+
+    //get the most recent trade of a token (contract + DELIMITER + token ID)
+    pub fn get_last_sale(&self, contract_and_token_id: ContractAndTokenId) -> Option<Trade> {
+        self.token_trades
+            .get(&contract_and_token_id)
+            .and_then(|history| history.iter_latest().next())
+    }
+
+    //returns paginated recent trades of an nft contract, most recent first. Only the last
+    // MAX_COLLECTION_TRADES trades are kept
+    pub fn get_collection_trades(
+        &self,
+        nft_contract_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Trade> {
+        let history = if let Some(history) = self.collection_trades.get(&nft_contract_id) {
+            history
+        } else {
+            return vec![];
+        };
+
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        history
+            .iter_latest()
+            .skip(start as usize)
+            .take(limit.unwrap_or(0) as usize)
+            .collect()
+    }
+}
+
+impl Contract {
+    /// adds a completed purchase to the trade history of its collection and token. The token's
+    /// history outlives its sales, so its growth is taken from the seller's storage balance, or
+    /// the buyer's if the seller's can't cover it. If neither can, the token's history is dropped
+    pub(crate) fn internal_record_trade(&mut self, trade: Trade) {
+        let nft_contract_id = trade.nft_contract_id.clone();
+        let mut collection_trades =
+            self.collection_trades
+                .get(&nft_contract_id)
+                .unwrap_or_else(|| {
+                    TradeHistory::new(
+                        StorageKey::CollectionTradesInner {
+                            account_id_hash: hash_account_id(&nft_contract_id),
+                        }
+                        .try_to_vec()
+                        .unwrap(),
+                        MAX_COLLECTION_TRADES,
+                    )
+                });
+        collection_trades.push(&trade);
+        self.collection_trades
+            .insert(&nft_contract_id, &collection_trades);
+
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, trade.token_id);
+        let mut token_trades = self
+            .token_trades
+            .get(&contract_and_token_id)
+            .unwrap_or_else(|| {
+                TradeHistory::new(
+                    StorageKey::TokenTradesInner {
+                        contract_and_token_id_hash: hash_contract_and_token_id(
+                            &contract_and_token_id,
+                        ),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                    MAX_TOKEN_TRADES,
+                )
+            });
+        let initial_storage_usage = env::storage_usage();
+        token_trades.push(&trade);
+        self.token_trades
+            .insert(&contract_and_token_id, &token_trades);
+
+        let storage_used = env::storage_usage().saturating_sub(initial_storage_usage);
+        let cost = Balance::from(storage_used) * STORAGE_PRICE_PER_BYTE;
+        if !self.internal_try_charge_storage(&trade.seller_id, cost)
+            && !self.internal_try_charge_storage(&trade.buyer_id, cost)
+        {
+            token_trades.clear();
+            self.token_trades.remove(&contract_and_token_id);
+        }
+    }
+}
@@ -7,7 +7,132 @@ pub(crate) fn hash_account_id(account_id: &AccountId) -> CryptoHash {
     hash
 }
 
+/// utilized to create unique prefix for collections keyed by a contract and token ID
+pub(crate) fn hash_contract_and_token_id(contract_and_token_id: &ContractAndTokenId) -> CryptoHash {
+    let mut hash = CryptoHash::default();
+    hash.copy_from_slice(&env::sha256(contract_and_token_id.as_bytes()));
+    hash
+}
+
+/// utilized to create unique prefix for collections keyed by a contract and fungible token ID
+pub(crate) fn hash_contract_and_ft_id(contract_and_ft_id: &ContractAndFtId) -> CryptoHash {
+    let mut hash = CryptoHash::default();
+    hash.copy_from_slice(&env::sha256(contract_and_ft_id.as_bytes()));
+    hash
+}
+
+/// maps the "near" pseudo token to None, which is how NEAR payments are passed to the purchase flow
+pub(crate) fn ft_contract_option(ft_contract_id: FungibleTokenId) -> Option<FungibleTokenId> {
+    Some(ft_contract_id).filter(|ft_contract_id| ft_contract_id.as_str() != "near")
+}
+
+/// the inverse of ft_contract_option, used wherever the payment token has to be named
+pub(crate) fn ft_contract_id_or_near(ft_contract_id: &Option<FungibleTokenId>) -> FungibleTokenId {
+    ft_contract_id
+        .clone()
+        .unwrap_or_else(|| AccountId::new_unchecked("near".to_string()))
+}
+
 impl Contract {
+    /// sends NEAR, or fungible tokens when an ft_contract_id is given, back to an account
+    pub(crate) fn refund_deposit(
+        &self,
+        receiver_id: AccountId,
+        amount: U128,
+        ft_contract_id: Option<FungibleTokenId>,
+        memo: &str,
+    ) {
+        match ft_contract_id {
+            Some(ft_contract_id) => {
+                ext_contract::ext(ft_contract_id)
+                    .with_attached_deposit(1)
+                    .with_static_gas(self.config.gas_for_ft_transfer)
+                    .ft_transfer(receiver_id, amount, Some(memo.to_string()));
+            }
+            None => {
+                Promise::new(receiver_id).transfer(amount.0);
+            }
+        }
+    }
+
+    /// parses the payout returned by nft_transfer_payout. Returns None unless it splits `balance`
+    /// (give or take 1 yocto of rounding) over at most max_len_payout accounts
+    pub(crate) fn valid_payout(
+        &self,
+        value: &[u8],
+        balance: Balance,
+    ) -> Option<HashMap<AccountId, U128>> {
+        let max_len_payout = self.config.max_len_payout as usize;
+
+        near_sdk::serde_json::from_slice::<Payout>(value)
+            .ok()
+            .and_then(|payout_object| {
+                if payout_object.payout.len() > max_len_payout || payout_object.payout.is_empty() {
+                    env::log_str(&format!(
+                        "Cannot have more than {} royalties",
+                        max_len_payout
+                    ));
+                    None
+                } else {
+                    let mut remainder = balance;
+
+                    for &value in payout_object.payout.values() {
+                        remainder = remainder.checked_sub(value.0)?;
+                    }
+                    if remainder == 0 || remainder == 1 {
+                        Some(payout_object.payout)
+                    } else {
+                        None
+                    }
+                }
+            })
+    }
+
+    /// pays every account of a validated payout in NEAR or the given fungible token. The
+    /// transfers are joined and checked by a single resolve_payout, which credits the failed
+    /// ones to the receivers' claimable balances
+    pub(crate) fn distribute_payout(
+        &self,
+        payout: HashMap<AccountId, U128>,
+        ft_contract_id: Option<FungibleTokenId>,
+        memo: &str,
+    ) {
+        let payout: Vec<(AccountId, U128)> = payout.into_iter().collect();
+
+        let transfers = payout
+            .iter()
+            .map(|(receiver_id, amount)| match &ft_contract_id {
+                Some(ft_contract_id) => ext_contract::ext(ft_contract_id.clone())
+                    .with_attached_deposit(1)
+                    .with_static_gas(self.config.gas_for_ft_transfer)
+                    .ft_transfer(receiver_id.clone(), *amount, Some(memo.to_string())),
+                None => Promise::new(receiver_id.clone()).transfer(amount.0),
+            })
+            .reduce(Promise::and);
+
+        if let Some(transfers) = transfers {
+            transfers.then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(gas_for_resolve_payout(payout.len()))
+                    .resolve_payout(ft_contract_id_or_near(&ft_contract_id), payout),
+            );
+        }
+    }
+
+    /// transfers a token the market holds in escrow
+    pub(crate) fn release_escrowed_token(
+        &self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        receiver_id: AccountId,
+        memo: &str,
+    ) {
+        ext_contract::ext(nft_contract_id)
+            .with_attached_deposit(1)
+            .with_static_gas(self.config.gas_for_nft_transfer)
+            .nft_transfer(receiver_id, token_id, None, Some(memo.to_string()));
+    }
+
     /// method to delete a sale. Returns the previously deleted sale object
     pub(crate) fn delete_sale(
         &mut self,
@@ -17,17 +142,11 @@ impl Contract {
         let contract_and_token_id = format!("{}{}{}", &nft_contract_id, DELIMETER, token_id);
         let sale = self.sales.remove(&contract_and_token_id).expect("No sale");
 
-        let mut by_owner_id = self
-            .by_owner_id
-            .get(&sale.owner_id)
-            .expect("No sale by_owner_id");
-        by_owner_id.remove(&contract_and_token_id);
-
-        if by_owner_id.is_empty() {
-            self.by_owner_id.remove(&sale.owner_id);
-        } else {
-            self.by_owner_id.insert(&sale.owner_id, &by_owner_id);
-        }
+        require!(
+            self.by_owner_id.contains_key(&sale.owner_id),
+            "No sale by_owner_id"
+        );
+        self.internal_remove_owner_sale(&sale.owner_id, &contract_and_token_id);
 
         let mut by_nft_contract_id = self
             .by_nft_contract_id
@@ -43,6 +162,292 @@ impl Contract {
                 .insert(&nft_contract_id, &by_nft_contract_id);
         }
 
+        self.internal_remove_sale_prices(&sale);
+        self.internal_record_delisting(&nft_contract_id);
+        self.internal_refund_storage(
+            &sale.owner_id,
+            sale.sale_conditions.reservation_storage_cost(),
+        );
+
         sale
     }
+
+    /// removes a sale from the sales listed by an account, dropping the account's set once it's empty
+    pub(crate) fn internal_remove_owner_sale(
+        &mut self,
+        owner_id: &AccountId,
+        contract_and_token_id: &ContractAndTokenId,
+    ) {
+        if let Some(mut by_owner_id) = self.by_owner_id.get(owner_id) {
+            by_owner_id.remove(contract_and_token_id);
+
+            if by_owner_id.is_empty() {
+                self.by_owner_id.remove(owner_id);
+            } else {
+                self.by_owner_id.insert(owner_id, &by_owner_id);
+            }
+        }
+    }
+
+    /// deletes a sale on behalf of the market rather than its owner, refunding the highest bid
+    /// if the sale is an auction. Returns the deleted sale
+    pub(crate) fn internal_delist_sale(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+    ) -> Sale {
+        let sale = self.delete_sale(nft_contract_id, token_id);
+
+        if let Some(Bid { bidder_id, amount }) = sale
+            .auction
+            .as_ref()
+            .and_then(|auction| auction.highest_bid.clone())
+        {
+            self.refund_deposit(
+                bidder_id,
+                amount,
+                ft_contract_option(sale.sale_conditions.single_price().0),
+                "Auction Cancelled",
+            );
+        }
+
+        sale
+    }
+
+    /// adds a sale to the price index of its nft contract, once for every currency it's priced
+    /// in. Auctions can't be bought at their price so they aren't indexed, and dutch auctions
+    /// are kept in a separate set since their price changes over time
+    pub(crate) fn internal_add_sale_prices(&mut self, sale: &Sale) {
+        if sale.auction.is_some() {
+            return;
+        }
+
+        for (ft_contract_id, price) in sale.sale_conditions.prices.iter() {
+            let contract_and_ft_id =
+                format!("{}{}{}", sale.nft_contract_id, DELIMETER, ft_contract_id);
+
+            if sale.sale_conditions.decay.is_some() {
+                let mut decaying_sales = self
+                    .decaying_sales
+                    .get(&contract_and_ft_id)
+                    .unwrap_or_else(|| {
+                        UnorderedSet::new(
+                            StorageKey::DecayingSalesInner {
+                                contract_and_ft_id_hash: hash_contract_and_ft_id(
+                                    &contract_and_ft_id,
+                                ),
+                            }
+                            .try_to_vec()
+                            .unwrap(),
+                        )
+                    });
+                decaying_sales.insert(&sale.token_id);
+                self.decaying_sales
+                    .insert(&contract_and_ft_id, &decaying_sales);
+                continue;
+            }
+
+            let mut by_price = self
+                .sales_by_price
+                .get(&contract_and_ft_id)
+                .unwrap_or_else(|| {
+                    TreeMap::new(
+                        StorageKey::SalesByPriceInner {
+                            contract_and_ft_id_hash: hash_contract_and_ft_id(&contract_and_ft_id),
+                        }
+                        .try_to_vec()
+                        .unwrap(),
+                    )
+                });
+            by_price.insert(&(price.0, sale.token_id.clone()), &());
+            self.sales_by_price.insert(&contract_and_ft_id, &by_price);
+        }
+    }
+
+    /// removes a sale from the price index, see internal_add_sale_prices
+    pub(crate) fn internal_remove_sale_prices(&mut self, sale: &Sale) {
+        if sale.auction.is_some() {
+            return;
+        }
+
+        for (ft_contract_id, price) in sale.sale_conditions.prices.iter() {
+            let contract_and_ft_id =
+                format!("{}{}{}", sale.nft_contract_id, DELIMETER, ft_contract_id);
+
+            if sale.sale_conditions.decay.is_some() {
+                if let Some(mut decaying_sales) = self.decaying_sales.get(&contract_and_ft_id) {
+                    decaying_sales.remove(&sale.token_id);
+
+                    if decaying_sales.is_empty() {
+                        self.decaying_sales.remove(&contract_and_ft_id);
+                    } else {
+                        self.decaying_sales
+                            .insert(&contract_and_ft_id, &decaying_sales);
+                    }
+                }
+                continue;
+            }
+
+            if let Some(mut by_price) = self.sales_by_price.get(&contract_and_ft_id) {
+                by_price.remove(&(price.0, sale.token_id.clone()));
+
+                if by_price.is_empty() {
+                    self.sales_by_price.remove(&contract_and_ft_id);
+                } else {
+                    self.sales_by_price.insert(&contract_and_ft_id, &by_price);
+                }
+            }
+        }
+    }
+
+    /// applies a price update to a sale owned by `owner_id`. Returns the currency that was
+    /// updated, or why the update can't be made, in which case the sale is left untouched
+    pub(crate) fn internal_update_price(
+        &mut self,
+        owner_id: &AccountId,
+        update: &PriceUpdate,
+    ) -> Result<FungibleTokenId, String> {
+        let contract_and_token_id =
+            format!("{}{}{}", update.nft_contract_id, DELIMETER, update.token_id);
+        let mut sale = self
+            .sales
+            .get(&contract_and_token_id)
+            .ok_or_else(|| "No sale".to_string())?;
+
+        if sale.owner_id != *owner_id {
+            return Err("Must be sale owner".to_string());
+        }
+        if sale.has_bids() {
+            return Err("Cannot update an auction that has bids".to_string());
+        }
+        if sale.swap.is_some() {
+            return Err("Cannot price a swap listing".to_string());
+        }
+
+        let prices = &mut sale.sale_conditions.prices;
+        let ft_contract_id = match &update.ft_contract_id {
+            Some(ft_contract_id) => ft_contract_id.clone(),
+            None if prices.len() == 1 => prices.keys().next().unwrap().clone(),
+            None => return Err("Sale must be priced in a single currency".to_string()),
+        };
+
+        match update.price {
+            Some(price) => {
+                if !self.approved_ft_tokens.contains(&ft_contract_id) {
+                    return Err("Only Approved Fungible Tokens can be used for listing".to_string());
+                }
+                prices.insert(ft_contract_id.clone(), price);
+            }
+            None => {
+                if prices.remove(&ft_contract_id).is_none() {
+                    return Err(format!("Sale is not priced in {}", ft_contract_id));
+                }
+                if prices.is_empty() {
+                    return Err("Cannot remove the last price of a sale".to_string());
+                }
+            }
+        }
+
+        //auctions and dutch auctions stay priced in a single currency
+        if (sale.auction.is_some() || sale.sale_conditions.decay.is_some())
+            && sale.sale_conditions.prices.len() != 1
+        {
+            return Err("Sale must be priced in a single currency".to_string());
+        }
+        if let (Some(decay), Some(price)) = (&sale.sale_conditions.decay, update.price) {
+            if decay.floor_price.0 > price.0 {
+                return Err("Floor price must be less than or equal to the start price".to_string());
+            }
+        }
+
+        let previous_sale = self.sales.insert(&contract_and_token_id, &sale).unwrap();
+        self.internal_remove_sale_prices(&previous_sale);
+        self.internal_add_sale_prices(&sale);
+
+        Ok(ft_contract_id)
+    }
+
+    /// panics if the market doesn't accept payments in the given token (anymore). Sales priced
+    /// in a token that was removed stay listed, but can't be bought until the price is updated
+    pub(crate) fn assert_approved_ft_token(&self, ft_contract_id: &FungibleTokenId) {
+        require!(
+            self.approved_ft_tokens.contains(ft_contract_id),
+            format!("{} is not an approved payment token", ft_contract_id)
+        );
+    }
+
+    /// number of sales and offers an account is currently paying storage for
+    pub(crate) fn storage_entries_by_account(&self, account_id: &AccountId) -> u64 {
+        let sales = self
+            .by_owner_id
+            .get(account_id)
+            .map(|s| s.len())
+            .unwrap_or_default();
+        let offers = self
+            .offers_by_buyer_id
+            .get(account_id)
+            .map(|s| s.len())
+            .unwrap_or_default();
+        let collection_offers = self
+            .collection_offers_by_buyer_id
+            .get(account_id)
+            .map(|s| s.len())
+            .unwrap_or_default();
+        let bundles = self
+            .bundles_by_owner_id
+            .get(account_id)
+            .map(|s| s.len())
+            .unwrap_or_default();
+
+        sales + offers + collection_offers + bundles
+    }
+
+    /// panics if the account's storage deposit can't cover one more sale or offer
+    pub(crate) fn assert_storage_for_new_entry(&self, account_id: &AccountId) {
+        let paid_storage = self.storage_deposits.get(account_id).unwrap_or(0);
+        let storage_required =
+            (self.storage_entries_by_account(account_id) + 1) as u128 * STORAGE_PER_SALE;
+
+        require!(
+            paid_storage >= storage_required,
+            format!(
+                "Insufficient storage paid: {}, for {} sales at {} rate of per sale",
+                paid_storage,
+                storage_required / STORAGE_PER_SALE,
+                STORAGE_PER_SALE
+            )
+        );
+    }
+
+    /// transfer the token to the buyer through nft_transfer_payout and settle the payment in
+    /// resolve_purchase
+    pub(crate) fn transfer_with_payout(
+        &self,
+        sold_token: SoldToken,
+        approval_id: u64,
+        price: U128,
+        buyer_id: AccountId,
+        ft_contract_id: Option<FungibleTokenId>,
+    ) -> Promise {
+        //the protocol fee is taken off the top, royalties are split over what's left
+        let protocol_fee = self.protocol_fee_for(price);
+
+        ext_contract::ext(sold_token.nft_contract_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(self.config.gas_for_nft_transfer)
+            .nft_transfer_payout(
+                buyer_id.clone(),
+                sold_token.token_id.clone(),
+                approval_id,
+                "payout from market".to_string(), //memo (to include some context)
+                U128(price.0 - protocol_fee.0),
+                //the maximum amount of accounts the market can payout at once, limited by GAS
+                self.config.max_len_payout,
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(self.config.gas_for_resolve_purchase)
+                    .resolve_purchase(buyer_id, price, ft_contract_id, protocol_fee, sold_token),
+            )
+    }
 }
@@ -1,5 +1,5 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, LookupSet, UnorderedMap, UnorderedSet};
+use near_sdk::collections::{LookupMap, LookupSet, TreeMap, UnorderedMap, UnorderedSet};
 use near_sdk::json_types::{U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
@@ -8,27 +8,59 @@ use near_sdk::{
 };
 use std::collections::HashMap;
 
+use crate::auction::*;
+use crate::bundle::*;
+use crate::claims::*;
+use crate::collection_offers::*;
+use crate::config::*;
+use crate::dutch_auction::*;
+use crate::events::*;
 use crate::external::*;
+use crate::history::*;
 use crate::internal::*;
+use crate::offers::*;
+use crate::owner::*;
+use crate::pause::*;
 use crate::sale::*;
+use crate::stats::*;
+use crate::swap::*;
 use near_sdk::env::STORAGE_PRICE_PER_BYTE;
 
+mod auction;
+mod bundle;
+mod claims;
+mod collection_offers;
+mod config;
+mod curation;
+mod dutch_auction;
+mod events;
 mod external;
+mod fees;
+mod history;
 mod internal;
 mod nft_callbacks;
+mod offer_views;
+mod offers;
+mod owner;
+mod pause;
 mod sale;
 mod sale_views;
+mod stats;
+mod storage;
+mod swap;
 
-const GAS_FOR_RESOLVE_PURCHASE: Gas = Gas(115_000_000_000_000);
-const GAS_FOR_NFT_TRANSFER: Gas = Gas(15_000_000_000_000);
-const GAS_FOR_FT_TRANSFER: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_SWEEP_ITEM: Gas = Gas(5_000_000_000_000);
 const STORAGE_PER_SALE: u128 = 1000 * STORAGE_PRICE_PER_BYTE;
 static DELIMETER: &str = ".";
+//most sales a single batch call can touch, keeps the batch and its event log within gas and log limits
+const MAX_BATCH_SIZE: usize = 50;
 
 pub type SalePriceInTokens = U128;
+pub type SaleConditions = HashMap<FungibleTokenId, SalePriceInTokens>;
 pub type TokenId = String;
 pub type FungibleTokenId = AccountId;
 pub type ContractAndTokenId = String;
+pub type ContractAndFtId = String;
 
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
@@ -41,15 +73,77 @@ pub struct Payout {
 pub struct Contract {
     pub owner_id: AccountId,
 
+    pub pending_owner_id: Option<AccountId>,
+
+    pub roles: UnorderedMap<AccountId, Vec<Role>>,
+
     pub sales: UnorderedMap<ContractAndTokenId, Sale>,
 
     pub by_owner_id: LookupMap<AccountId, UnorderedSet<ContractAndTokenId>>,
 
     pub by_nft_contract_id: LookupMap<AccountId, UnorderedSet<TokenId>>,
 
-    pub approved_ft_tokens: LookupSet<FungibleTokenId>,
+    pub sales_by_price: LookupMap<ContractAndFtId, TreeMap<(u128, TokenId), ()>>,
+
+    pub decaying_sales: LookupMap<ContractAndFtId, UnorderedSet<TokenId>>,
+
+    pub collection_offers: UnorderedMap<u64, CollectionOffer>,
+
+    pub collection_offers_by_nft_contract_id: LookupMap<AccountId, UnorderedSet<u64>>,
+
+    pub collection_offers_by_buyer_id: LookupMap<AccountId, UnorderedSet<u64>>,
+
+    pub approved_ft_tokens: UnorderedSet<FungibleTokenId>,
 
     pub storage_deposits: LookupMap<AccountId, Balance>,
+
+    pub offers: UnorderedMap<u64, Offer>,
+
+    pub offers_by_token: LookupMap<ContractAndTokenId, UnorderedSet<u64>>,
+
+    pub offers_by_buyer_id: LookupMap<AccountId, UnorderedSet<u64>>,
+
+    pub next_offer_id: u64,
+
+    pub protocol_fee_bps: u16,
+
+    pub fee_treasury_id: AccountId,
+
+    pub accrued_fees: LookupMap<FungibleTokenId, Balance>,
+
+    pub paused: PauseFlags,
+
+    pub config: MarketConfig,
+
+    pub collection_trades: LookupMap<AccountId, TradeHistory>,
+
+    pub token_trades: LookupMap<ContractAndTokenId, TradeHistory>,
+
+    pub collection_stats: LookupMap<AccountId, CollectionStats>,
+
+    pub collection_sellers: LookupSet<(AccountId, AccountId)>,
+
+    pub curation_enabled: bool,
+
+    pub allowed_nft_contract_ids: UnorderedSet<AccountId>,
+
+    pub blocked_nft_contract_ids: UnorderedSet<AccountId>,
+
+    pub blocked_seller_ids: UnorderedSet<AccountId>,
+
+    pub swap_funds: LookupMap<(AccountId, FungibleTokenId), Balance>,
+
+    pub bundles: UnorderedMap<u64, Bundle>,
+
+    pub bundles_by_owner_id: LookupMap<AccountId, UnorderedSet<u64>>,
+
+    pub bundles_by_nft_contract_id: LookupMap<AccountId, UnorderedSet<u64>>,
+
+    pub next_bundle_id: u64,
+
+    pub pending_bundle_purchases: LookupMap<u64, PendingBundlePurchase>,
+
+    pub claimable: LookupMap<(AccountId, FungibleTokenId), Balance>,
 }
 
 #[derive(BorshStorageKey, BorshSerialize)]
@@ -59,8 +153,41 @@ pub enum StorageKey {
     ByOwnerIdInner { account_id_hash: CryptoHash },
     ByNFTContractId,
     ByNFTContractIdInner { account_id_hash: CryptoHash },
+    SalesByPrice,
+    SalesByPriceInner { contract_and_ft_id_hash: CryptoHash },
+    DecayingSales,
+    DecayingSalesInner { contract_and_ft_id_hash: CryptoHash },
+    CollectionOffers,
+    CollectionOffersByNFTContractId,
+    CollectionOffersByNFTContractIdInner { account_id_hash: CryptoHash },
+    CollectionOffersByBuyerId,
+    CollectionOffersByBuyerIdInner { account_id_hash: CryptoHash },
     FTTokenIds,
     StorageDeposits,
+    Offers,
+    OffersByToken,
+    OffersByTokenInner { contract_and_token_id_hash: CryptoHash },
+    OffersByBuyerId,
+    OffersByBuyerIdInner { account_id_hash: CryptoHash },
+    AccruedFees,
+    Roles,
+    CollectionTrades,
+    CollectionTradesInner { account_id_hash: CryptoHash },
+    TokenTrades,
+    TokenTradesInner { contract_and_token_id_hash: CryptoHash },
+    CollectionStats,
+    CollectionSellers,
+    AllowedNFTContractIds,
+    BlockedNFTContractIds,
+    BlockedSellerIds,
+    SwapFunds,
+    Bundles,
+    BundlesByOwnerId,
+    BundlesByOwnerIdInner { account_id_hash: CryptoHash },
+    PendingBundlePurchases,
+    Claimable,
+    BundlesByNFTContractId,
+    BundlesByNFTContractIdInner { account_id_hash: CryptoHash },
 }
 
 #[near_bindgen]
@@ -71,12 +198,45 @@ impl Contract {
     #[init]
     pub fn new(owner_id: AccountId) -> Self {
         let mut this = Self {
-            owner_id,
+            owner_id: owner_id.clone(),
+            pending_owner_id: None,
+            roles: UnorderedMap::new(StorageKey::Roles),
             sales: UnorderedMap::new(StorageKey::Sales),
             by_owner_id: LookupMap::new(StorageKey::ByOwnerId),
             by_nft_contract_id: LookupMap::new(StorageKey::ByNFTContractId),
-            approved_ft_tokens: LookupSet::new(StorageKey::FTTokenIds),
+            sales_by_price: LookupMap::new(StorageKey::SalesByPrice),
+            decaying_sales: LookupMap::new(StorageKey::DecayingSales),
+            collection_offers: UnorderedMap::new(StorageKey::CollectionOffers),
+            collection_offers_by_nft_contract_id: LookupMap::new(
+                StorageKey::CollectionOffersByNFTContractId,
+            ),
+            collection_offers_by_buyer_id: LookupMap::new(StorageKey::CollectionOffersByBuyerId),
+            approved_ft_tokens: UnorderedSet::new(StorageKey::FTTokenIds),
             storage_deposits: LookupMap::new(StorageKey::StorageDeposits),
+            offers: UnorderedMap::new(StorageKey::Offers),
+            offers_by_token: LookupMap::new(StorageKey::OffersByToken),
+            offers_by_buyer_id: LookupMap::new(StorageKey::OffersByBuyerId),
+            next_offer_id: 0,
+            protocol_fee_bps: 0,
+            fee_treasury_id: owner_id.clone(),
+            accrued_fees: LookupMap::new(StorageKey::AccruedFees),
+            paused: PauseFlags::default(),
+            config: MarketConfig::default(),
+            collection_trades: LookupMap::new(StorageKey::CollectionTrades),
+            token_trades: LookupMap::new(StorageKey::TokenTrades),
+            collection_stats: LookupMap::new(StorageKey::CollectionStats),
+            collection_sellers: LookupSet::new(StorageKey::CollectionSellers),
+            curation_enabled: false,
+            allowed_nft_contract_ids: UnorderedSet::new(StorageKey::AllowedNFTContractIds),
+            blocked_nft_contract_ids: UnorderedSet::new(StorageKey::BlockedNFTContractIds),
+            blocked_seller_ids: UnorderedSet::new(StorageKey::BlockedSellerIds),
+            swap_funds: LookupMap::new(StorageKey::SwapFunds),
+            bundles: UnorderedMap::new(StorageKey::Bundles),
+            bundles_by_owner_id: LookupMap::new(StorageKey::BundlesByOwnerId),
+            bundles_by_nft_contract_id: LookupMap::new(StorageKey::BundlesByNFTContractId),
+            next_bundle_id: 0,
+            pending_bundle_purchases: LookupMap::new(StorageKey::PendingBundlePurchases),
+            claimable: LookupMap::new(StorageKey::Claimable),
         };
 
         let near_contract_id = AccountId::new_unchecked("near".to_string());
@@ -85,7 +245,7 @@ impl Contract {
     }
 
     pub fn add_ft_token_ids(&mut self, ft_token_ids: Vec<FungibleTokenId>) -> Vec<bool> {
-        require!(env::predecessor_account_id() == self.owner_id,"Only Owner can approve ft_token_id");
+        self.assert_role(Role::Admin);
 
         let mut added = vec![];
 
@@ -96,56 +256,17 @@ impl Contract {
         added
     }
 
-    //Cover the cost of storing sale objects on the contract
-    //Optional account ID is to users can pay for storage for other people.
-    #[payable]
-    pub fn storage_deposit(&mut self, account_id: Option<AccountId>) {
-        let storage_account_id = account_id
-            .map(|a| a.into())
-            .unwrap_or_else(env::predecessor_account_id);
-
-        let deposit = env::attached_deposit();
-
-        require!(
-            deposit >= STORAGE_PER_SALE,
-            format!("Requires minimum deposit of {}", STORAGE_PER_SALE)
-        );
-
-        let mut balance: u128 = self.storage_deposits.get(&storage_account_id).unwrap_or(0);
-        balance = balance
-            .checked_add(deposit)
-            .unwrap_or_else(|| env::panic_str("Balance Overflow"));
-        self.storage_deposits.insert(&storage_account_id, &balance);
-    }
-
-    //Withdraw any excess storage fees.
-    #[payable]
-    pub fn storage_withdraw(&mut self) {
-        assert_one_yocto();
-
-        let owner_id = env::predecessor_account_id();
-        let mut amount = self.storage_deposits.remove(&owner_id).unwrap_or(0);
-
-        let sales = self.by_owner_id.get(&owner_id);
-        let len = sales.map(|s| s.len()).unwrap_or_default();
-        let diff = u128::from(len) * STORAGE_PER_SALE;
-
-        amount -= diff;
+    //stop accepting payments in the given tokens. Existing sales priced in a removed token
+    // stay listed, but can't be bought until the seller updates the price to an approved token
+    pub fn remove_ft_token_ids(&mut self, ft_token_ids: Vec<FungibleTokenId>) -> Vec<bool> {
+        self.assert_role(Role::Admin);
 
-        if amount > 0 {
-            Promise::new(owner_id.clone()).transfer(amount);
-        }
+        let mut removed = vec![];
 
-        if diff > 0 {
-            self.storage_deposits.insert(&owner_id, &diff);
+        for ft_token_id in ft_token_ids {
+            removed.push(self.approved_ft_tokens.remove(&ft_token_id));
         }
-    }
-
-    pub fn storage_minimum_balance(&self) -> U128 {
-        U128(STORAGE_PER_SALE)
-    }
 
-    pub fn storage_balance_of(&self, account_id: AccountId) -> U128 {
-        U128(self.storage_deposits.get(&account_id).unwrap_or(0))
+        removed
     }
 }
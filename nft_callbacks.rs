@@ -1,11 +1,169 @@
 use crate::*;
 
+//most accounts a private sale can be reserved for
+pub const MAX_RESERVED_FOR: usize = 100;
+
 //keep track of the sale conditions
 #[derive(Serialize, Deserialize, BorshDeserialize, BorshSerialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct SaleConditionArgs {
-    pub price: SalePriceInTokens,
+    //price of the token in each fungible token it can be bought with ("near" for NEAR)
+    pub prices: SaleConditions,
+    //optional dutch auction schedule, the listed price is used as the start price.
+    // Decaying sales can only be priced in a single currency
+    #[serde(default)]
+    pub decay: Option<PriceDecay>,
+    //optional block timestamp (in nanoseconds) before which the token can't be bought
+    #[serde(default)]
+    pub starts_at: Option<U64>,
+    //optional block timestamp (in nanoseconds) after which the listing expires
+    #[serde(default)]
+    pub expires_at: Option<U64>,
+    //optional accounts the sale is reserved for, making it a private sale only they can buy.
+    // The seller's storage balance pays for the list while the sale is listed
+    #[serde(default)]
+    pub reserved_for: Option<Vec<AccountId>>,
+}
+
+impl SaleConditionArgs {
+    pub(crate) fn is_expired(&self) -> bool {
+        self.expires_at
+            .map(|expires_at| env::block_timestamp() >= expires_at.0)
+            .unwrap_or(false)
+    }
+
+    /// whether the listing can be bought at the current block timestamp
+    pub(crate) fn is_active(&self) -> bool {
+        let started = self
+            .starts_at
+            .map(|starts_at| env::block_timestamp() >= starts_at.0)
+            .unwrap_or(true);
+        started && !self.is_expired()
+    }
+
+    /// panics if the listing can't be bought at the current block timestamp
+    pub(crate) fn assert_active(&self) {
+        if let Some(starts_at) = self.starts_at {
+            require!(
+                env::block_timestamp() >= starts_at.0,
+                "Sale has not started yet"
+            );
+        }
+        require!(!self.is_expired(), "Sale has expired");
+    }
+
+    /// whether anyone can buy the listing at the current block timestamp, which rules out
+    /// private sales
+    pub(crate) fn is_public(&self) -> bool {
+        self.is_active() && self.reserved_for.is_none()
+    }
+
+    /// the one currency and price of a listing that can only be priced in a single currency
+    pub(crate) fn single_price(&self) -> (FungibleTokenId, U128) {
+        require!(
+            self.prices.len() == 1,
+            "Sale must be priced in a single currency"
+        );
+        let (ft_contract_id, price) = self.prices.iter().next().unwrap();
+        (ft_contract_id.clone(), *price)
+    }
+
+    /// whether the account is allowed to buy the listing, which is anyone unless the sale is private
+    pub(crate) fn is_reserved_for(&self, buyer_id: &AccountId) -> bool {
+        self.reserved_for
+            .as_ref()
+            .map(|reserved_for| reserved_for.contains(buyer_id))
+            .unwrap_or(true)
+    }
+
+    /// panics if a private sale has no buyers or more than MAX_RESERVED_FOR
+    pub(crate) fn assert_valid_reservation(&self) {
+        if let Some(reserved_for) = &self.reserved_for {
+            require!(
+                !reserved_for.is_empty(),
+                "A private sale must be reserved for at least one account"
+            );
+            require!(
+                reserved_for.len() <= MAX_RESERVED_FOR,
+                format!(
+                    "A private sale can be reserved for at most {} accounts",
+                    MAX_RESERVED_FOR
+                )
+            );
+        }
+    }
+
+    /// storage cost of the accounts a private sale is reserved for, on top of STORAGE_PER_SALE
+    pub(crate) fn reservation_storage_cost(&self) -> Balance {
+        self.reserved_for
+            .as_ref()
+            .map(|reserved_for| {
+                reserved_for.try_to_vec().unwrap().len() as Balance * STORAGE_PRICE_PER_BYTE
+            })
+            .unwrap_or(0)
+    }
+
+    /// panics if the listing would already be expired or never start
+    pub(crate) fn assert_valid_schedule(&self) {
+        if let Some(expires_at) = self.expires_at {
+            require!(!self.is_expired(), "Sale must expire in the future");
+            if let Some(starts_at) = self.starts_at {
+                require!(
+                    starts_at.0 < expires_at.0,
+                    "Sale must start before it expires"
+                );
+            }
+        }
+    }
+}
+
+//msg used to list a token as an english auction
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AuctionArgs {
+    pub reserve_price: SalePriceInTokens,
     pub ft_contract_id: FungibleTokenId,
+    pub min_bid_increment: U128,
+    pub ends_at: U64,
+    //optional accounts the auction is reserved for, only they can bid
+    #[serde(default)]
+    pub reserved_for: Option<Vec<AccountId>>,
+}
+
+//msg used by a token owner to accept a standing offer on their token
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AcceptOfferArgs {
+    pub offer_id: U64,
+}
+
+//msg used by a token holder to sell into a collection offer
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FillCollectionOfferArgs {
+    pub collection_offer_id: U64,
+}
+
+//msg shared by every token of a batch approval (e.g. an nft contract forwarding the same msg
+// for each approved token). Each token is listed with the sale conditions under its token ID
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BatchSaleArgs {
+    pub batch: HashMap<TokenId, SaleConditionArgs>,
+}
+
+//the different msg formats accepted by nft_on_approve
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde", untagged)]
+enum ApproveMsg {
+    AcceptOffer(AcceptOfferArgs),
+    FillCollectionOffer(FillCollectionOfferArgs),
+    Auction(AuctionArgs),
+    Swap { swap: SwapArgs },
+    AcceptSwap { accept_swap: PurchaseArgs },
+    AddToBundle { add_to_bundle: BundleArgs },
+    BatchSale(BatchSaleArgs),
+    Sale(SaleConditionArgs),
 }
 
 #[derive(Serialize, Deserialize, BorshDeserialize, BorshSerialize)]
@@ -15,12 +173,31 @@ pub struct PurchaseArgs {
     pub token_id: TokenId,
 }
 
+//the different msg formats accepted by ft_on_transfer
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde", untagged)]
+pub enum FtOnTransferArgs {
+    Purchase(PurchaseArgs),
+    MakeOffer {
+        make_offer: OfferArgs,
+    },
+    MakeCollectionOffer {
+        make_collection_offer: CollectionOfferArgs,
+    },
+    DepositSwapFunds {
+        deposit_swap_funds: DepositSwapFundsArgs,
+    },
+    PurchaseBundle {
+        purchase_bundle: BundleArgs,
+    },
+}
+
 /*
     Used as the callback from the NFT contract. When nft_approve is
     called, it will fire a cross contract call to this marketplace and this is the function
     that is invoked.
 */
-trait NonFungibleTokenApprovalsReceiver {
+pub trait NonFungibleTokenApprovalsReceiver {
     fn nft_on_approve(
         &mut self,
         token_id: TokenId,
@@ -46,44 +223,174 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
             "nft_on_approve should only be called via cross-contract call"
         );
         require!(owner_id == signer_id, "owner_id should be signer_id");
+        //blocked or non-curated tokens can't be listed, escrowed or traded in any way
+        self.assert_can_list(&nft_contract_id, &owner_id);
 
-        let storage_amount = self.storage_minimum_balance().0;
-        let owner_paid_storage = self.storage_deposits.get(&signer_id).unwrap_or(0);
-        let signer_storage_required =
-            (self.get_supply_by_owner_id(signer_id).0 + 1) as u128 * storage_amount;
-
-        require!(
-            owner_paid_storage >= signer_storage_required,
-            format!(
-                "Insufficient storage paid: {}, for {} sales at {} rate of per sale",
-                owner_paid_storage,
-                signer_storage_required / STORAGE_PER_SALE,
-                STORAGE_PER_SALE
-            )
-        );
+        let (sale_conditions, auction, swap) =
+            match near_sdk::serde_json::from_str(&msg).expect("Not valid SaleArgs") {
+                ApproveMsg::AcceptOffer(AcceptOfferArgs { offer_id }) => {
+                    self.accept_offer(offer_id.0, nft_contract_id, token_id, owner_id, approval_id);
+                    return;
+                }
+                ApproveMsg::FillCollectionOffer(FillCollectionOfferArgs {
+                    collection_offer_id,
+                }) => {
+                    self.fill_collection_offer(
+                        collection_offer_id.0,
+                        nft_contract_id,
+                        token_id,
+                        owner_id,
+                        approval_id,
+                    );
+                    return;
+                }
+                ApproveMsg::AcceptSwap { accept_swap } => {
+                    self.accept_swap(
+                        accept_swap,
+                        nft_contract_id,
+                        token_id,
+                        owner_id,
+                        approval_id,
+                    );
+                    return;
+                }
+                ApproveMsg::AddToBundle { add_to_bundle } => {
+                    self.internal_approve_bundle_token(
+                        add_to_bundle.bundle_id.0,
+                        nft_contract_id,
+                        token_id,
+                        owner_id,
+                        approval_id,
+                    );
+                    return;
+                }
+                ApproveMsg::Swap { swap } => {
+                    let (sale_conditions, swap) = self.internal_swap_listing(swap);
+                    (sale_conditions, None, Some(swap))
+                }
+                ApproveMsg::BatchSale(BatchSaleArgs { mut batch }) => {
+                    require!(
+                        batch.len() <= MAX_BATCH_SIZE,
+                        format!("Cannot list more than {} tokens at once", MAX_BATCH_SIZE)
+                    );
+                    let sale_conditions = batch
+                        .remove(&token_id)
+                        .expect("Token is not part of the batch");
+                    sale_conditions.assert_valid_decay();
+                    sale_conditions.assert_valid_schedule();
+                    sale_conditions.assert_valid_reservation();
+                    (sale_conditions, None, None)
+                }
+                ApproveMsg::Sale(sale_conditions) => {
+                    sale_conditions.assert_valid_decay();
+                    sale_conditions.assert_valid_schedule();
+                    sale_conditions.assert_valid_reservation();
+                    (sale_conditions, None, None)
+                }
+                ApproveMsg::Auction(AuctionArgs {
+                    reserve_price,
+                    ft_contract_id,
+                    min_bid_increment,
+                    ends_at,
+                    reserved_for,
+                }) => {
+                    require!(
+                        ends_at.0 > env::block_timestamp(),
+                        "Auction must end in the future"
+                    );
+                    require!(
+                        min_bid_increment.0 > 0,
+                        "Minimum bid increment must be greater than 0"
+                    );
 
-        let SaleConditionArgs {
-            price,
-            ft_contract_id,
-        } = near_sdk::serde_json::from_str(&msg).expect("Not valid SaleArgs");
+                    let sale_conditions = SaleConditionArgs {
+                        prices: HashMap::from([(ft_contract_id, reserve_price)]),
+                        decay: None,
+                        starts_at: None,
+                        expires_at: None,
+                        reserved_for,
+                    };
+                    sale_conditions.assert_valid_reservation();
+                    let auction = Auction {
+                        min_bid_increment,
+                        ends_at,
+                        highest_bid: None,
+                    };
+                    (sale_conditions, Some(auction), None)
+                }
+            };
 
-        let sale_conditions = SaleConditionArgs {
-            price,
-            ft_contract_id,
-        };
+        self.assert_listing_not_paused();
+        if swap.is_none() {
+            require!(
+                !sale_conditions.prices.is_empty(),
+                "Sale must have at least one price"
+            );
+        }
+        for ft_contract_id in sale_conditions.prices.keys() {
+            self.assert_approved_ft_token(ft_contract_id);
+        }
+        self.assert_storage_for_new_entry(&signer_id);
 
         let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        //an auction holding an escrowed bid has to be settled before the token is listed again
+        if let Some(previous_sale) = self.sales.get(&contract_and_token_id) {
+            require!(
+                !previous_sale.has_bids(),
+                "Cannot replace an auction that has bids"
+            );
+        }
 
-        self.sales.insert(
-            &contract_and_token_id,
-            &Sale {
+        match &swap {
+            Some(swap) => EventLogVariant::ListSwap(vec![ListSwapLog {
                 owner_id: owner_id.clone(),
-                approval_id,
-                nft_contract_id: nft_contract_id.to_string(),
+                nft_contract_id: nft_contract_id.clone(),
                 token_id: token_id.clone(),
-                sale_conditions,
-            },
-        );
+                approval_id,
+                wanted: swap.wanted.clone(),
+                sweetener: swap.sweetener.clone(),
+            }]),
+            //one entry per currency the token can be bought with
+            None => EventLogVariant::List(
+                sale_conditions
+                    .prices
+                    .iter()
+                    .map(|(ft_contract_id, price)| ListLog {
+                        owner_id: owner_id.clone(),
+                        nft_contract_id: nft_contract_id.clone(),
+                        token_id: token_id.clone(),
+                        approval_id,
+                        ft_contract_id: ft_contract_id.clone(),
+                        price: *price,
+                    })
+                    .collect(),
+            ),
+        }
+        .emit();
+
+        let sale = Sale {
+            owner_id: owner_id.clone(),
+            approval_id,
+            nft_contract_id: nft_contract_id.to_string(),
+            token_id: token_id.clone(),
+            sale_conditions,
+            auction,
+            swap,
+        };
+        //re-approving a listed token replaces its sale
+        let previous_sale = self.sales.insert(&contract_and_token_id, &sale);
+        if let Some(previous_sale) = &previous_sale {
+            self.internal_remove_sale_prices(previous_sale);
+            self.internal_refund_storage(
+                &previous_sale.owner_id,
+                previous_sale.sale_conditions.reservation_storage_cost(),
+            );
+            //the token changed hands, so it's no longer listed by the previous owner
+            if previous_sale.owner_id != owner_id {
+                self.internal_remove_owner_sale(&previous_sale.owner_id, &contract_and_token_id);
+            }
+        }
+        self.internal_add_sale_prices(&sale);
 
         let mut by_owner_id = self.by_owner_id.get(&owner_id).unwrap_or_else(|| {
             UnorderedSet::new(
@@ -115,5 +422,8 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
 
         self.by_nft_contract_id
             .insert(&nft_contract_id, &by_nft_contract_id);
+
+        self.internal_charge_storage(&owner_id, sale.sale_conditions.reservation_storage_cost());
+        self.internal_record_listing(&nft_contract_id, &owner_id, previous_sale.is_none());
     }
 }
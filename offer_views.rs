@@ -0,0 +1,99 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    // views
+
+    //returns the total amount of standing offers on the market
+    pub fn get_supply_offers(&self) -> U64 {
+        U64(self.offers.len())
+    }
+
+    //get an offer by its ID
+    pub fn get_offer(&self, offer_id: U64) -> Option<Offer> {
+        self.offers.get(&offer_id.0)
+    }
+
+    //returns paginated offers made on a given token
+    pub fn get_offers_by_token(
+        &self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Offer> {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+
+        let offers = if let Some(offers) = self.offers_by_token.get(&contract_and_token_id) {
+            offers
+        } else {
+            return vec![];
+        };
+
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        offers
+            .as_vector()
+            .iter()
+            .skip(start as usize)
+            .take(limit.unwrap_or(0) as usize)
+            .map(|offer_id| self.offers.get(&offer_id).unwrap())
+            .collect()
+    }
+
+    //returns paginated offers made by a given account
+    pub fn get_offers_by_buyer_id(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Offer> {
+        let offers = if let Some(offers) = self.offers_by_buyer_id.get(&account_id) {
+            offers
+        } else {
+            return vec![];
+        };
+
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        offers
+            .as_vector()
+            .iter()
+            .skip(start as usize)
+            .take(limit.unwrap_or(0) as usize)
+            .map(|offer_id| self.offers.get(&offer_id).unwrap())
+            .collect()
+    }
+
+    //get a collection offer by its ID
+    pub fn get_collection_offer(&self, collection_offer_id: U64) -> Option<CollectionOffer> {
+        self.collection_offers.get(&collection_offer_id.0)
+    }
+
+    //returns paginated collection offers made on a given nft contract
+    pub fn get_collection_offers_by_nft_contract_id(
+        &self,
+        nft_contract_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<CollectionOffer> {
+        let collection_offers = if let Some(collection_offers) = self
+            .collection_offers_by_nft_contract_id
+            .get(&nft_contract_id)
+        {
+            collection_offers
+        } else {
+            return vec![];
+        };
+
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        collection_offers
+            .as_vector()
+            .iter()
+            .skip(start as usize)
+            .take(limit.unwrap_or(0) as usize)
+            .map(|collection_offer_id| self.collection_offers.get(&collection_offer_id).unwrap())
+            .collect()
+    }
+}
@@ -0,0 +1,246 @@
+use crate::*;
+
+/// an escrowed offer on a specific token, which doesn't have to be listed on the market
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Offer {
+    pub offer_id: U64,
+    //account that made the offer and whose funds are escrowed
+    pub buyer_id: AccountId,
+    //nft contract where the token was minted
+    pub nft_contract_id: AccountId,
+    //token ID the offer is made on
+    pub token_id: TokenId,
+    //token the offer is paid in ("near" for NEAR)
+    pub ft_contract_id: FungibleTokenId,
+    //escrowed amount that will be paid out if the offer is accepted
+    pub price: SalePriceInTokens,
+    //block timestamp (in nanoseconds) after which the offer can no longer be accepted
+    pub expires_at: Option<U64>,
+}
+
+impl Offer {
+    pub(crate) fn is_expired(&self) -> bool {
+        self.expires_at
+            .map(|expires_at| env::block_timestamp() >= expires_at.0)
+            .unwrap_or(false)
+    }
+}
+
+//msg used with ft_transfer_call to make an offer in fungible tokens
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OfferArgs {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub expires_at: Option<U64>,
+}
+
+#[near_bindgen]
+impl Contract {
+    //make an escrowed offer in NEAR on any token. The attached deposit is the offered price
+    #[payable]
+    pub fn make_offer(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        expires_at: Option<U64>,
+    ) -> U64 {
+        let deposit = env::attached_deposit();
+        require!(deposit > 0, "Attached deposit must be greater than 0");
+
+        let near_contract_id = AccountId::new_unchecked("near".to_string());
+
+        self.internal_add_offer(
+            env::predecessor_account_id(),
+            OfferArgs {
+                nft_contract_id,
+                token_id,
+                expires_at,
+            },
+            near_contract_id,
+            U128(deposit),
+        )
+    }
+
+    //cancel an offer and refund the escrow to the buyer. Expired offers can be cleaned up by anyone
+    #[payable]
+    pub fn cancel_offer(&mut self, offer_id: U64) {
+        assert_one_yocto();
+
+        let offer = self.internal_remove_offer(offer_id.0);
+        require!(
+            env::predecessor_account_id() == offer.buyer_id || offer.is_expired(),
+            "Only the buyer can cancel an active offer"
+        );
+
+        self.refund_deposit(
+            offer.buyer_id,
+            offer.price,
+            ft_contract_option(offer.ft_contract_id),
+            "Offer Refund",
+        );
+    }
+}
+
+impl Contract {
+    /// stores a new offer whose funds have already been received by the market
+    pub(crate) fn internal_add_offer(
+        &mut self,
+        buyer_id: AccountId,
+        offer_args: OfferArgs,
+        ft_contract_id: FungibleTokenId,
+        price: U128,
+    ) -> U64 {
+        let OfferArgs {
+            nft_contract_id,
+            token_id,
+            expires_at,
+        } = offer_args;
+
+        self.assert_offers_not_paused();
+        require!(
+            self.approved_ft_tokens.contains(&ft_contract_id),
+            "Only Approved Fungible Tokens can be used for offers"
+        );
+        if let Some(expires_at) = expires_at {
+            require!(
+                expires_at.0 > env::block_timestamp(),
+                "Offer must expire in the future"
+            );
+        }
+        self.assert_storage_for_new_entry(&buyer_id);
+
+        let offer_id = self.next_offer_id;
+        self.next_offer_id += 1;
+
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+
+        self.offers.insert(
+            &offer_id,
+            &Offer {
+                offer_id: U64(offer_id),
+                buyer_id: buyer_id.clone(),
+                nft_contract_id,
+                token_id,
+                ft_contract_id,
+                price,
+                expires_at,
+            },
+        );
+
+        let mut offers_by_token = self
+            .offers_by_token
+            .get(&contract_and_token_id)
+            .unwrap_or_else(|| {
+                UnorderedSet::new(
+                    StorageKey::OffersByTokenInner {
+                        contract_and_token_id_hash: hash_contract_and_token_id(
+                            &contract_and_token_id,
+                        ),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                )
+            });
+        offers_by_token.insert(&offer_id);
+        self.offers_by_token
+            .insert(&contract_and_token_id, &offers_by_token);
+
+        let mut offers_by_buyer_id = self.offers_by_buyer_id.get(&buyer_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::OffersByBuyerIdInner {
+                    account_id_hash: hash_account_id(&buyer_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        offers_by_buyer_id.insert(&offer_id);
+        self.offers_by_buyer_id
+            .insert(&buyer_id, &offers_by_buyer_id);
+
+        U64(offer_id)
+    }
+
+    /// removes an offer from the market and its indexes. Returns the removed offer
+    pub(crate) fn internal_remove_offer(&mut self, offer_id: u64) -> Offer {
+        let offer = self.offers.remove(&offer_id).expect("No offer");
+        let contract_and_token_id =
+            format!("{}{}{}", offer.nft_contract_id, DELIMETER, offer.token_id);
+
+        let mut offers_by_token = self
+            .offers_by_token
+            .get(&contract_and_token_id)
+            .expect("No offer by token");
+        offers_by_token.remove(&offer_id);
+
+        if offers_by_token.is_empty() {
+            self.offers_by_token.remove(&contract_and_token_id);
+        } else {
+            self.offers_by_token
+                .insert(&contract_and_token_id, &offers_by_token);
+        }
+
+        let mut offers_by_buyer_id = self
+            .offers_by_buyer_id
+            .get(&offer.buyer_id)
+            .expect("No offer by buyer_id");
+        offers_by_buyer_id.remove(&offer_id);
+
+        if offers_by_buyer_id.is_empty() {
+            self.offers_by_buyer_id.remove(&offer.buyer_id);
+        } else {
+            self.offers_by_buyer_id
+                .insert(&offer.buyer_id, &offers_by_buyer_id);
+        }
+
+        offer
+    }
+
+    /// accepts an offer on behalf of the token owner, who approved the market in nft_on_approve.
+    /// Any listing of the token is removed and the escrow goes through the regular payout flow
+    pub(crate) fn accept_offer(
+        &mut self,
+        offer_id: u64,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        owner_id: AccountId,
+        approval_id: u64,
+    ) -> Promise {
+        self.assert_offers_not_paused();
+
+        let offer = self.internal_remove_offer(offer_id);
+
+        require!(
+            offer.nft_contract_id == nft_contract_id && offer.token_id == token_id,
+            "Offer was made on a different token"
+        );
+        require!(!offer.is_expired(), "Offer has expired");
+        require!(offer.buyer_id != owner_id, "Cannot accept your own offer");
+
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        if let Some(sale) = self.sales.get(&contract_and_token_id) {
+            require!(
+                !sale.has_bids(),
+                "Cannot accept an offer on an auction that has bids"
+            );
+            self.delete_sale(nft_contract_id.clone(), token_id.clone());
+            EventLogVariant::Delist(vec![DelistLog::from(&sale)]).emit();
+        }
+
+        let sold_token = SoldToken {
+            nft_contract_id,
+            token_id,
+            seller_id: owner_id,
+        };
+
+        self.transfer_with_payout(
+            sold_token,
+            approval_id,
+            offer.price,
+            offer.buyer_id,
+            ft_contract_option(offer.ft_contract_id),
+        )
+    }
+}
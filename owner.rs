@@ -0,0 +1,139 @@
+use crate::*;
+
+/// roles the owner can hand out so other accounts can manage parts of the market.
+/// Admins can do everything the other roles can, and grant or revoke them
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug,
+)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum Role {
+    //manages the approved fungible tokens and prunes trade history
+    Admin,
+    //sets the protocol fee and withdraws accrued fees
+    FeeManager,
+    //manages which nft contracts and sellers can use the market
+    Curator,
+    //pauses and unpauses market actions
+    Pauser,
+}
+
+#[near_bindgen]
+impl Contract {
+    //propose a new owner, who has to call accept_ownership to take over. Passing None cancels
+    // a pending proposal
+    #[payable]
+    pub fn propose_owner(&mut self, new_owner_id: Option<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+
+        self.pending_owner_id = new_owner_id;
+    }
+
+    //accept a pending ownership proposal
+    #[payable]
+    pub fn accept_ownership(&mut self) {
+        assert_one_yocto();
+
+        let new_owner_id = env::predecessor_account_id();
+        require!(
+            self.pending_owner_id.as_ref() == Some(&new_owner_id),
+            "Only the proposed owner can accept ownership"
+        );
+
+        self.owner_id = new_owner_id;
+        self.pending_owner_id = None;
+    }
+
+    //give an account a role. Only the owner can grant the admin role
+    #[payable]
+    pub fn grant_role(&mut self, account_id: AccountId, role: Role) {
+        assert_one_yocto();
+        self.assert_can_manage_role(role);
+
+        let mut roles = self.roles.get(&account_id).unwrap_or_default();
+        if !roles.contains(&role) {
+            roles.push(role);
+            self.roles.insert(&account_id, &roles);
+        }
+    }
+
+    //take a role away from an account. Only the owner can revoke the admin role
+    #[payable]
+    pub fn revoke_role(&mut self, account_id: AccountId, role: Role) {
+        assert_one_yocto();
+        self.assert_can_manage_role(role);
+
+        let mut roles = self.roles.get(&account_id).unwrap_or_default();
+        roles.retain(|r| *r != role);
+
+        if roles.is_empty() {
+            self.roles.remove(&account_id);
+        } else {
+            self.roles.insert(&account_id, &roles);
+        }
+    }
+
+    // views
+
+    pub fn get_owner(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    pub fn get_pending_owner(&self) -> Option<AccountId> {
+        self.pending_owner_id.clone()
+    }
+
+    //returns the roles granted to an account
+    pub fn get_roles(&self, account_id: AccountId) -> Vec<Role> {
+        self.roles.get(&account_id).unwrap_or_default()
+    }
+
+    //returns paginated accounts that were granted roles, along with their roles
+    pub fn get_role_holders(
+        &self,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<(AccountId, Vec<Role>)> {
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        self.roles
+            .iter()
+            .skip(start as usize)
+            .take(limit.unwrap_or(0) as usize)
+            .collect()
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_owner(&self) {
+        require!(
+            env::predecessor_account_id() == self.owner_id,
+            "Only Owner can call this method"
+        );
+    }
+
+    /// whether the account is the owner, an admin, or was granted the role
+    pub(crate) fn has_role(&self, account_id: &AccountId, role: Role) -> bool {
+        if *account_id == self.owner_id {
+            return true;
+        }
+
+        let roles = self.roles.get(account_id).unwrap_or_default();
+        roles.contains(&role) || roles.contains(&Role::Admin)
+    }
+
+    pub(crate) fn assert_role(&self, role: Role) {
+        require!(
+            self.has_role(&env::predecessor_account_id(), role),
+            format!("Requires the {:?} role", role)
+        );
+    }
+
+    fn assert_can_manage_role(&self, role: Role) {
+        if role == Role::Admin {
+            self.assert_owner();
+        } else {
+            self.assert_role(Role::Admin);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use crate::*;
+
+/// market actions that can be paused independently. Removing sales, cancelling offers
+/// and withdrawing storage always keep working so users can exit the market
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Default, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PauseFlags {
+    //new listings through nft_on_approve and price updates
+    pub listing: bool,
+    //purchases and auction bids through offer, ft_on_transfer and settle_auction
+    pub buying: bool,
+    //making, accepting and filling standing and collection offers
+    pub offers: bool,
+}
+
+#[near_bindgen]
+impl Contract {
+    //pause or unpause market actions
+    pub fn set_paused(&mut self, paused: PauseFlags) {
+        self.assert_role(Role::Pauser);
+
+        self.paused = paused;
+    }
+
+    // views
+
+    pub fn get_paused(&self) -> PauseFlags {
+        self.paused
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_listing_not_paused(&self) {
+        require!(!self.paused.listing, "Listing is paused");
+    }
+
+    pub(crate) fn assert_buying_not_paused(&self) {
+        require!(!self.paused.buying, "Buying is paused");
+    }
+
+    pub(crate) fn assert_offers_not_paused(&self) {
+        require!(!self.paused.offers, "Offers are paused");
+    }
+}
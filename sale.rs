@@ -1,5 +1,5 @@
 use crate::{
-    nft_callbacks::{PurchaseArgs, SaleConditionArgs},
+    nft_callbacks::{FtOnTransferArgs, PurchaseArgs, SaleConditionArgs},
     *,
 };
 use near_sdk::{promise_result_as_success, PromiseOrValue};
@@ -18,6 +18,66 @@ pub struct Sale {
     pub token_id: String,
     //sale conditions for token listed
     pub sale_conditions: SaleConditionArgs,
+    //english auction state, if the token is listed as an auction
+    pub auction: Option<Auction>,
+    //what the seller wants in exchange, if the token is listed as a swap. Swaps have no prices
+    pub swap: Option<SwapTerms>,
+}
+
+//the token changing hands in a purchase, passed through to resolve_purchase
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SoldToken {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    //current owner of the token, who receives the payout
+    pub seller_id: AccountId,
+}
+
+//a single price change of a batch passed to update_prices, see update_price
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceUpdate {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub price: Option<U128>,
+    pub ft_contract_id: Option<FungibleTokenId>,
+}
+
+/// price update and price removal logs collected over a batch, so each is emitted as one event
+#[derive(Default)]
+struct PriceChangeLogs {
+    updated: Vec<UpdatePriceLog>,
+    removed: Vec<RemovePriceLog>,
+}
+
+impl PriceChangeLogs {
+    fn push(&mut self, owner_id: AccountId, update: PriceUpdate, ft_contract_id: FungibleTokenId) {
+        match update.price {
+            Some(price) => self.updated.push(UpdatePriceLog {
+                owner_id,
+                nft_contract_id: update.nft_contract_id,
+                token_id: update.token_id,
+                ft_contract_id,
+                price,
+            }),
+            None => self.removed.push(RemovePriceLog {
+                owner_id,
+                nft_contract_id: update.nft_contract_id,
+                token_id: update.token_id,
+                ft_contract_id,
+            }),
+        }
+    }
+
+    fn emit(self) {
+        if !self.updated.is_empty() {
+            EventLogVariant::UpdatePrice(self.updated).emit();
+        }
+        if !self.removed.is_empty() {
+            EventLogVariant::RemovePrice(self.removed).emit();
+        }
+    }
 }
 
 #[near_bindgen]
@@ -29,46 +89,123 @@ impl Contract {
         let sale = self.delete_sale(nft_contract_id.into(), token_id);
         let owner_id = env::predecessor_account_id();
         require!(owner_id == sale.owner_id, "Must be sale owner");
+        require!(!sale.has_bids(), "Cannot remove an auction that has bids");
+
+        EventLogVariant::Delist(vec![DelistLog::from(&sale)]).emit();
     }
 
-    //updates the price for a sale on the market
+    //removes several sales from the market in one call. Sales that don't exist, belong to
+    // someone else or hold auction bids are skipped, so the result tells which sales were removed
+    #[payable]
+    pub fn remove_sales(&mut self, sales: Vec<(AccountId, TokenId)>) -> Vec<bool> {
+        assert_one_yocto();
+        require!(
+            sales.len() <= MAX_BATCH_SIZE,
+            format!("Cannot remove more than {} sales at once", MAX_BATCH_SIZE)
+        );
+
+        let owner_id = env::predecessor_account_id();
+        let mut removed = vec![];
+
+        let results = sales
+            .into_iter()
+            .map(|(nft_contract_id, token_id)| {
+                let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+                let removable = self
+                    .sales
+                    .get(&contract_and_token_id)
+                    .map(|sale| sale.owner_id == owner_id && !sale.has_bids())
+                    .unwrap_or(false);
+
+                if removable {
+                    removed.push(self.delete_sale(nft_contract_id, token_id));
+                }
+                removable
+            })
+            .collect();
+
+        if !removed.is_empty() {
+            EventLogVariant::Delist(removed.iter().map(DelistLog::from).collect()).emit();
+        }
+
+        results
+    }
+
+    //sets the price of a sale in one currency, adding the currency if the sale wasn't priced in it
+    // yet. Passing no price removes the currency instead. The currency can be left out for sales
+    // priced in a single currency
     #[payable]
     pub fn update_price(
         &mut self,
         nft_contract_id: AccountId,
         token_id: String,
-        price: U128,
+        price: Option<U128>,
         ft_contract_id: Option<FungibleTokenId>,
     ) {
         assert_one_yocto();
+        self.assert_listing_not_paused();
 
-        let contract_id: AccountId = nft_contract_id.into();
-        let contract_and_token_id = format!("{}{}{}", contract_id, DELIMETER, token_id);
+        let owner_id = env::predecessor_account_id();
+        let update = PriceUpdate {
+            nft_contract_id,
+            token_id,
+            price,
+            ft_contract_id,
+        };
+
+        let ft_contract_id = self
+            .internal_update_price(&owner_id, &update)
+            .unwrap_or_else(|err| env::panic_str(&err));
 
-        let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        let mut changes = PriceChangeLogs::default();
+        changes.push(owner_id, update, ft_contract_id);
+        changes.emit();
+    }
 
+    //applies several price updates in one call. Updates that would fail on their own (see
+    // update_price) are skipped without touching the sale, so the result tells which were applied
+    #[payable]
+    pub fn update_prices(&mut self, updates: Vec<PriceUpdate>) -> Vec<bool> {
+        assert_one_yocto();
+        self.assert_listing_not_paused();
         require!(
-            env::predecessor_account_id() == sale.owner_id,
-            "Must be sale owner"
+            updates.len() <= MAX_BATCH_SIZE,
+            format!("Cannot update more than {} prices at once", MAX_BATCH_SIZE)
         );
 
-        sale.sale_conditions.price = price;
+        let owner_id = env::predecessor_account_id();
+        let mut changes = PriceChangeLogs::default();
 
-        if let Some(ft_contract_id) = ft_contract_id {
-            require!(
-                self.approved_ft_tokens.contains(&ft_contract_id),
-                "Only Approved Fungible Tokens can be used for listing"
-            );
-            sale.sale_conditions.ft_contract_id = ft_contract_id
+        let mut results = vec![];
+
+        for update in updates {
+            let applied = match self.internal_update_price(&owner_id, &update) {
+                Ok(ft_contract_id) => {
+                    changes.push(owner_id.clone(), update, ft_contract_id);
+                    true
+                }
+                Err(err) => {
+                    env::log_str(&format!(
+                        "Skipped {}{}{}: {}",
+                        update.nft_contract_id, DELIMETER, update.token_id, err
+                    ));
+                    false
+                }
+            };
+            results.push(applied);
         }
 
-        self.sales.insert(&contract_and_token_id, &sale);
+        changes.emit();
+
+        results
     }
 
     //place an offer on a specific sale. The sale will go through as long as your deposit
-    // is greater than or equal to the list price
+    // is greater than or equal to the list price. For auctions the deposit is placed as a bid instead
     #[payable]
     pub fn offer(&mut self, nft_contract_id: AccountId, token_id: String) {
+        self.assert_buying_not_paused();
+
         let deposit = env::attached_deposit();
         require!(deposit > 0, "Attached deposit must be greater than 0");
 
@@ -79,15 +216,25 @@ impl Contract {
 
         let near_contract_id = AccountId::new_unchecked("near".to_string());
 
-        require!(
-            sale.sale_conditions.ft_contract_id == near_contract_id,
-            "Not available to buy"
-        );
+        let price = sale
+            .sale_conditions
+            .price_for(&near_contract_id)
+            .unwrap_or_else(|| env::panic_str("Not available to buy"));
+        self.assert_approved_ft_token(&near_contract_id);
+
+        sale.sale_conditions.assert_active();
 
         let buyer_id = env::predecessor_account_id();
         require!(sale.owner_id != buyer_id, "Cannot bid on your own sale.");
+        require!(
+            sale.sale_conditions.is_reserved_for(&buyer_id),
+            "Sale is reserved for other buyers"
+        );
 
-        let price = sale.sale_conditions.price;
+        if sale.auction.is_some() {
+            self.place_bid(sale, buyer_id, U128(deposit), None);
+            return;
+        }
 
         require!(
             deposit >= price.0,
@@ -97,10 +244,101 @@ impl Contract {
             )
         );
 
+        //refund anything attached above the price
+        let excess = deposit - price.0;
+        if excess > 0 {
+            Promise::new(buyer_id.clone()).transfer(excess);
+        }
+
         //process the purchase (which will remove the sale, transfer and get the
         // payout from the nft contract, and then distribute royalties)
-        self.process_purchase(contract_id, token_id, U128(deposit),
-                              buyer_id, None);
+        self.process_purchase(contract_id, token_id, price, buyer_id, None);
+    }
+
+    //buy several sales priced in NEAR in one call, in the given order, spending at most
+    // `max_total_spend`. The attached gas has to cover a purchase of every given sale. Sales that
+    // can't be bought (missing, auctions, not priced in NEAR, not active, your own, reserved for
+    // someone else, or over the remaining budget) are skipped. The unspent deposit is refunded
+    // right away and purchases that fail are refunded by resolve_purchase. Returns which sales a
+    // purchase was started for
+    #[payable]
+    pub fn offer_batch(
+        &mut self,
+        nft_contract_token_ids: Vec<ContractAndTokenId>,
+        max_total_spend: U128,
+    ) -> Vec<bool> {
+        self.assert_buying_not_paused();
+        require!(
+            nft_contract_token_ids.len() <= MAX_BATCH_SIZE,
+            format!("Cannot buy more than {} sales at once", MAX_BATCH_SIZE)
+        );
+
+        let deposit = env::attached_deposit();
+        require!(
+            deposit >= max_total_spend.0,
+            "Attached deposit must cover the max total spend"
+        );
+
+        let near_contract_id = AccountId::new_unchecked("near".to_string());
+        self.assert_approved_ft_token(&near_contract_id);
+
+        let buyer_id = env::predecessor_account_id();
+        let gas_per_purchase = self.config.gas_for_batch_purchase();
+        let gas_left = env::prepaid_gas() - env::used_gas();
+        require!(
+            gas_per_purchase * nft_contract_token_ids.len() as u64 <= gas_left,
+            format!(
+                "Attached gas can only cover {} purchases of {} gas each",
+                gas_left.0 / gas_per_purchase.0,
+                gas_per_purchase.0
+            )
+        );
+
+        let mut remaining = max_total_spend.0;
+        let mut results = vec![];
+
+        for contract_and_token_id in nft_contract_token_ids {
+            let purchase = self
+                .sales
+                .get(&contract_and_token_id)
+                .filter(|sale| {
+                    sale.auction.is_none()
+                        && sale.owner_id != buyer_id
+                        && sale.sale_conditions.is_active()
+                        && sale.sale_conditions.is_reserved_for(&buyer_id)
+                })
+                .and_then(|sale| {
+                    let price = sale.sale_conditions.price_for(&near_contract_id)?;
+                    Some((sale, price))
+                })
+                .filter(|(_, price)| price.0 <= remaining);
+
+            let (sale, price) = if let Some(purchase) = purchase {
+                purchase
+            } else {
+                env::log_str(&format!("Skipped {}", contract_and_token_id));
+                results.push(false);
+                continue;
+            };
+
+            remaining -= price.0;
+            self.process_purchase(
+                AccountId::new_unchecked(sale.nft_contract_id),
+                sale.token_id,
+                price,
+                buyer_id.clone(),
+                None,
+            );
+            results.push(true);
+        }
+
+        //refund the deposit that wasn't spent
+        let spent = max_total_spend.0 - remaining;
+        if deposit > spent {
+            Promise::new(buyer_id).transfer(deposit - spent);
+        }
+
+        results
     }
 
     pub fn ft_on_transfer(
@@ -115,38 +353,120 @@ impl Contract {
             return PromiseOrValue::Value(amount);
         };
 
+        let ft_contract_id = env::predecessor_account_id();
+
         let PurchaseArgs {
             nft_contract_id,
             token_id,
-        } = near_sdk::serde_json::from_str(&msg).expect("Invalid PurchaseArgs");
+        } = match near_sdk::serde_json::from_str(&msg).expect("Invalid PurchaseArgs") {
+            FtOnTransferArgs::Purchase(purchase_args) => purchase_args,
+            FtOnTransferArgs::MakeOffer { make_offer } => {
+                self.internal_add_offer(sender_id, make_offer, ft_contract_id, amount);
+                return PromiseOrValue::Value(U128(0));
+            }
+            FtOnTransferArgs::MakeCollectionOffer {
+                make_collection_offer,
+            } => {
+                self.internal_add_collection_offer(
+                    sender_id,
+                    make_collection_offer,
+                    ft_contract_id,
+                    amount,
+                );
+                return PromiseOrValue::Value(U128(0));
+            }
+            FtOnTransferArgs::DepositSwapFunds {
+                deposit_swap_funds: DepositSwapFundsArgs {},
+            } => {
+                self.internal_deposit_swap_funds(&sender_id, &ft_contract_id, amount.0);
+                return PromiseOrValue::Value(U128(0));
+            }
+            FtOnTransferArgs::PurchaseBundle { purchase_bundle } => {
+                let bundle_id = purchase_bundle.bundle_id.0;
+                let price = self.internal_bundle_price(bundle_id, &ft_contract_id, &sender_id);
+                require!(
+                    amount.0 >= price.0,
+                    "Attached tokens are less than the listed price"
+                );
+
+                self.internal_purchase_bundle(bundle_id, price, sender_id, Some(ft_contract_id));
+                return PromiseOrValue::Value(U128(amount.0 - price.0));
+            }
+        };
 
-        let ft_contract_id = env::predecessor_account_id();
+        self.assert_buying_not_paused();
 
         let contract_id: AccountId = nft_contract_id.into();
         let contract_and_token_id = format!("{}{}{}", contract_id, DELIMETER, token_id);
 
         let sale = self.sales.get(&contract_and_token_id).expect("No sale");
 
+        let price = sale
+            .sale_conditions
+            .price_for(&ft_contract_id)
+            .unwrap_or_else(|| {
+                env::panic_str(&format!("Cannot Purchase with {} tokens", ft_contract_id))
+            });
+        self.assert_approved_ft_token(&ft_contract_id);
+
+        sale.sale_conditions.assert_active();
+
+        require!(sale.owner_id != sender_id, "Cannot bid on your own sale.");
         require!(
-            sale.sale_conditions.ft_contract_id == ft_contract_id,
-            format!("Cannot Purchase with {} tokens", ft_contract_id)
+            sale.sale_conditions.is_reserved_for(&sender_id),
+            "Sale is reserved for other buyers"
         );
 
-        let price = sale.sale_conditions.price;
+        if sale.auction.is_some() {
+            self.place_bid(sale, sender_id, amount, Some(ft_contract_id));
+            return PromiseOrValue::Value(U128(0));
+        }
 
-        require!(sale.owner_id != sender_id, "Cannot bid on your own sale.");
         require!(
-            price.0 >= amount.0,
+            amount.0 >= price.0,
             "Attached tokens are less than the listed price"
         );
 
-        PromiseOrValue::Promise(self.process_purchase(
+        //only the price is charged. The purchase settles on its own (resolve_purchase refunds
+        // the price if it fails), so the excess can be handed back to the FT contract right away
+        self.process_purchase(
             contract_id,
             token_id,
-            amount,
+            price,
             sender_id,
             Some(ft_contract_id),
-        ))
+        );
+
+        PromiseOrValue::Value(U128(amount.0 - price.0))
+    }
+
+    //check `limit` sales starting at `from_index` and remove the ones that have expired, giving
+    // the storage back to their owners. Removed sales are replaced by the last sales in the map,
+    // so a window can be checked again until nothing is removed. Can be called by anyone.
+    // Returns the number of removed sales
+    pub fn prune_expired_sales(&mut self, from_index: Option<U128>, limit: u64) -> u64 {
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        let expired: Vec<Sale> = self
+            .sales
+            .values()
+            .skip(start as usize)
+            .take(limit as usize)
+            .filter(|sale| sale.sale_conditions.is_expired())
+            .collect();
+
+        for sale in expired.iter() {
+            self.delete_sale(
+                AccountId::new_unchecked(sale.nft_contract_id.clone()),
+                sale.token_id.clone(),
+            );
+        }
+
+        if !expired.is_empty() {
+            EventLogVariant::Delist(expired.iter().map(DelistLog::from).collect()).emit();
+        }
+
+        expired.len() as u64
     }
 
     //remove the sale, transfer and get the payout from the nft contract, and distribute royalties
@@ -162,27 +482,25 @@ impl Contract {
         let sale = self.delete_sale(nft_contract_id.clone(),
                                     token_id.clone());
 
-        ext_contract::ext(nft_contract_id)
-            .with_attached_deposit(1)
-            .with_static_gas(GAS_FOR_NFT_TRANSFER)
-            .nft_transfer_payout(
-                buyer_id.clone(),
-                token_id,
-                sale.approval_id,
-                "payout from market".to_string(), //memo (to include some context)
-                price,
-                7, //the maximum amount of accounts the market can payout at once, limited by GAS
-            )
-            .then(
-                Self::ext(env::current_account_id())
-                    .with_static_gas(GAS_FOR_RESOLVE_PURCHASE)
-                    .resolve_purchase(buyer_id, price, ft_contract_id),
-            )
+        let sold_token = SoldToken {
+            nft_contract_id,
+            token_id,
+            seller_id: sale.owner_id,
+        };
+
+        self.transfer_with_payout(
+            sold_token,
+            sale.approval_id,
+            price,
+            buyer_id,
+            ft_contract_id,
+        )
     }
 
     /*
-        Authenticate the payout object. If everything is fine, it will pay the accounts.
-        If there's a problem, refund the buyer for the price.
+        Authenticate the payout object. If everything is fine, it will keep the protocol fee
+        and pay the accounts. If the transfer failed, refund the buyer for the price. If the
+        token was transferred but the payout is invalid, pay the seller instead.
     */
     #[private]
     pub fn resolve_purchase(
@@ -190,64 +508,84 @@ impl Contract {
         buyer_id: AccountId,
         price: U128,
         ft_contract_id: Option<FungibleTokenId>,
+        protocol_fee: U128,
+        sold_token: SoldToken,
     ) -> U128 {
-        let payout_option = promise_result_as_success().and_then(|value| {
-            near_sdk::serde_json::from_slice::<Payout>(&value)
-                .ok()
-                .and_then(|payout_object| {
-                    if payout_object.payout.len() > 7 || payout_object.payout.is_empty() {
-                        env::log_str("Cannot have more than 7 royalties");
-                        None
-                    } else {
-                        let mut remainder = price.0;
-
-                        for &value in payout_object.payout.values() {
-                            remainder = remainder.checked_sub(value.0)?;
-                        }
-                        if remainder == 0 || remainder == 1 {
-                            Some(payout_object.payout)
-                        } else {
-                            None
-                        }
-                    }
-                })
-        });
-
-        let payout = if let Some(payout_option) = payout_option {
-            payout_option
+        //the nft contract was asked to split the price minus the protocol fee
+        let balance = price.0 - protocol_fee.0;
+
+        let payout = if let Some(value) = promise_result_as_success() {
+            //the token already belongs to the buyer, so a bad payout can't be answered with a
+            // refund. The seller gets the whole balance and the nft contract is flagged
+            self.valid_payout(&value, balance).unwrap_or_else(|| {
+                EventLogVariant::NonCompliantPayout(vec![NonCompliantPayoutLog {
+                    nft_contract_id: sold_token.nft_contract_id.clone(),
+                    token_id: sold_token.token_id.clone(),
+                    seller_id: sold_token.seller_id.clone(),
+                    buyer_id: buyer_id.clone(),
+                    ft_contract_id: ft_contract_id_or_near(&ft_contract_id),
+                    amount: U128(balance),
+                }])
+                .emit();
+
+                HashMap::from([(sold_token.seller_id.clone(), U128(balance))])
+            })
         } else {
-            match ft_contract_id {
-                Some(ft_contract_id) => {
-                    let memo = Some("Marketplace Refund".to_string());
-
-                    ext_contract::ext(ft_contract_id)
-                        .with_attached_deposit(1)
-                        .with_static_gas(GAS_FOR_FT_TRANSFER)
-                        .ft_transfer(buyer_id, price, memo);
-                }
-                None => {
-                    Promise::new(buyer_id).transfer(u128::from(price));
-                }
-            }
+            EventLogVariant::PurchaseRefund(vec![PurchaseRefundLog {
+                buyer_id: buyer_id.clone(),
+                nft_contract_id: sold_token.nft_contract_id,
+                token_id: sold_token.token_id,
+                ft_contract_id: ft_contract_id_or_near(&ft_contract_id),
+                price,
+            }])
+            .emit();
+
+            self.refund_deposit(buyer_id, price, ft_contract_id, "Marketplace Refund");
             return price;
         };
 
-        match ft_contract_id {
-            Some(ft_contract_id) => {
-                let memo = Some("Marketplace Royalties".to_string());
-                for (receiver_id, amount) in payout {
-                    ext_contract::ext(ft_contract_id.clone())
-                        .with_attached_deposit(1)
-                        .with_static_gas(GAS_FOR_FT_TRANSFER)
-                        .ft_transfer(receiver_id, amount, memo.clone());
-                }
-            }
-            None => {
-                for (receiver_id, amount) in payout {
-                    Promise::new(receiver_id).transfer(amount.0);
-                }
-            }
-        }
+        self.internal_accrue_fee(&ft_contract_id, protocol_fee);
+        self.internal_record_trade(Trade {
+            nft_contract_id: sold_token.nft_contract_id.clone(),
+            token_id: sold_token.token_id.clone(),
+            seller_id: sold_token.seller_id.clone(),
+            buyer_id: buyer_id.clone(),
+            ft_contract_id: ft_contract_id_or_near(&ft_contract_id),
+            price,
+            timestamp: U64(env::block_timestamp()),
+        });
+        self.internal_record_sale_stats(
+            &sold_token.nft_contract_id,
+            ft_contract_id_or_near(&ft_contract_id),
+            price,
+        );
+
+        EventLogVariant::Purchase(vec![PurchaseLog {
+            seller_id: sold_token.seller_id.clone(),
+            buyer_id,
+            nft_contract_id: sold_token.nft_contract_id.clone(),
+            token_id: sold_token.token_id.clone(),
+            ft_contract_id: ft_contract_id_or_near(&ft_contract_id),
+            price,
+            protocol_fee,
+        }])
+        .emit();
+
+        EventLogVariant::RoyaltyPayout(
+            payout
+                .iter()
+                .map(|(receiver_id, amount)| RoyaltyPayoutLog {
+                    receiver_id: receiver_id.clone(),
+                    nft_contract_id: sold_token.nft_contract_id.clone(),
+                    token_id: sold_token.token_id.clone(),
+                    ft_contract_id: ft_contract_id_or_near(&ft_contract_id),
+                    amount: *amount,
+                })
+                .collect(),
+        )
+        .emit();
+
+        self.distribute_payout(payout, ft_contract_id, "Marketplace Royalties");
         price
     }
 }
@@ -255,7 +593,8 @@ impl Contract {
 /*
     used to resolve the promise for nft_transfer_payout. Authenticate the payout object.
     If everything is fine, pay the accounts.
-    If there's a problem, refund the buyer for the price.
+    If the transfer failed, refund the buyer for the price.
+    If the payout is invalid but the token was transferred, pay the seller.
 */
 #[ext_contract(ext_self)]
 trait ExtSelf {
@@ -264,5 +603,255 @@ trait ExtSelf {
         buyer_id: AccountId,
         price: U128,
         ft_contract_id: Option<FungibleTokenId>,
+        protocol_fee: U128,
+        sold_token: SoldToken,
     ) -> Promise;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nft_callbacks::NonFungibleTokenApprovalsReceiver;
+    use crate::storage::StorageManagement;
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    const PRICE: u128 = 1000;
+
+    fn nft() -> AccountId {
+        AccountId::new_unchecked("nft.near".to_string())
+    }
+
+    fn ft() -> AccountId {
+        AccountId::new_unchecked("ft.near".to_string())
+    }
+
+    fn context(predecessor: AccountId, deposit: Balance) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(AccountId::new_unchecked("market.near".to_string()))
+            .signer_account_id(predecessor.clone())
+            .predecessor_account_id(predecessor)
+            .attached_deposit(deposit);
+        builder
+    }
+
+    //a market with token "1" of nft.near listed by accounts(1) for PRICE in NEAR and ft.near
+    fn setup() -> Contract {
+        testing_env!(context(accounts(0), 0).build());
+        let mut contract = Contract::new(accounts(0));
+        contract.add_ft_token_ids(vec![ft()]);
+
+        let min_storage = contract.storage_balance_bounds().min.0;
+        testing_env!(context(accounts(1), min_storage).build());
+        contract.storage_deposit(None, None);
+
+        testing_env!(context(nft(), 0).signer_account_id(accounts(1)).build());
+        contract.nft_on_approve(
+            "1".to_string(),
+            accounts(1),
+            0,
+            format!(r#"{{"prices":{{"near":"{0}","ft.near":"{0}"}}}}"#, PRICE),
+        );
+        contract
+    }
+
+    //list token "1" again for accounts(3), as if accounts(1) had transferred it to them
+    fn relist_for_new_owner(contract: &mut Contract) {
+        let min_storage = contract.storage_balance_bounds().min.0;
+        testing_env!(context(accounts(3), min_storage).build());
+        contract.storage_deposit(None, None);
+
+        testing_env!(context(nft(), 0).signer_account_id(accounts(3)).build());
+        contract.nft_on_approve(
+            "1".to_string(),
+            accounts(3),
+            1,
+            format!(r#"{{"prices":{{"near":"{}"}}}}"#, PRICE),
+        );
+    }
+
+    fn is_listed(contract: &Contract) -> bool {
+        contract.get_sale("nft.near.1".to_string()).is_some()
+    }
+
+    fn purchase_with_ft(contract: &mut Contract, amount: Balance) -> Balance {
+        testing_env!(context(ft(), 0).build());
+        match contract.ft_on_transfer(
+            accounts(2),
+            U128(amount),
+            Some(r#"{"nft_contract_id":"nft.near","token_id":"1"}"#.to_string()),
+        ) {
+            PromiseOrValue::Value(unused) => unused.0,
+            PromiseOrValue::Promise(_) => panic!("Expected the unused amount"),
+        }
+    }
+
+    fn purchase_with_near(contract: &mut Contract, deposit: Balance) {
+        testing_env!(context(accounts(2), deposit).build());
+        contract.offer(nft(), "1".to_string());
+    }
+
+    //NEAR transfers the purchase sent back to the buyer
+    fn refunds_to_buyer() -> Vec<Balance> {
+        get_created_receipts()
+            .into_iter()
+            .filter(|receipt| receipt.receiver_id == accounts(2))
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                VmAction::Transfer { deposit } => Some(deposit),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached tokens are less than the listed price")]
+    fn ft_purchase_underpaid() {
+        let mut contract = setup();
+        purchase_with_ft(&mut contract, PRICE - 1);
+    }
+
+    #[test]
+    fn ft_purchase_exact() {
+        let mut contract = setup();
+        assert_eq!(purchase_with_ft(&mut contract, PRICE), 0);
+        assert!(!is_listed(&contract));
+    }
+
+    #[test]
+    fn ft_purchase_overpaid_returns_excess() {
+        let mut contract = setup();
+        assert_eq!(purchase_with_ft(&mut contract, PRICE + 250), 250);
+        assert!(!is_listed(&contract));
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached deposit must be greater than or equal")]
+    fn near_purchase_underpaid() {
+        let mut contract = setup();
+        purchase_with_near(&mut contract, PRICE - 1);
+    }
+
+    #[test]
+    fn near_purchase_exact() {
+        let mut contract = setup();
+        purchase_with_near(&mut contract, PRICE);
+        assert!(!is_listed(&contract));
+        assert!(refunds_to_buyer().is_empty());
+    }
+
+    #[test]
+    fn near_purchase_overpaid_refunds_excess() {
+        let mut contract = setup();
+        purchase_with_near(&mut contract, PRICE + 250);
+        assert!(!is_listed(&contract));
+        assert_eq!(refunds_to_buyer(), vec![250]);
+    }
+
+    #[test]
+    fn previous_owner_unregistering_keeps_relisted_sale() {
+        let mut contract = setup();
+        relist_for_new_owner(&mut contract);
+        assert_eq!(contract.get_supply_by_owner_id(accounts(1)).0, 0);
+
+        testing_env!(context(accounts(1), 1).build());
+        assert!(contract.storage_unregister(Some(true)));
+
+        let sale = contract.get_sale("nft.near.1".to_string()).unwrap();
+        assert_eq!(sale.owner_id, accounts(3));
+    }
+
+    #[test]
+    fn first_listing_fits_minimum_storage_balance() {
+        testing_env!(context(accounts(0), 0).build());
+        let mut contract = Contract::new(accounts(0));
+
+        //the longest account IDs give the largest collection stats records
+        let seller_id = AccountId::new_unchecked(format!("{}.near", "s".repeat(59)));
+        let nft_contract_id = AccountId::new_unchecked(format!("{}.near", "n".repeat(59)));
+        let min_storage = contract.storage_balance_bounds().min.0;
+        testing_env!(context(seller_id.clone(), min_storage).build());
+        contract.storage_deposit(None, None);
+
+        testing_env!(context(nft_contract_id, 0)
+            .signer_account_id(seller_id.clone())
+            .build());
+        contract.nft_on_approve(
+            "1".to_string(),
+            seller_id.clone(),
+            0,
+            format!(r#"{{"prices":{{"near":"{}"}}}}"#, PRICE),
+        );
+        assert_eq!(contract.get_supply_by_owner_id(seller_id).0, 1);
+    }
+
+    #[test]
+    fn private_sale_storage_is_charged_while_listed() {
+        let mut contract = setup();
+        //listing the token again needs storage for another entry
+        testing_env!(context(accounts(1), STORAGE_PER_SALE).build());
+        contract.storage_deposit(None, None);
+        let balance = contract.storage_balance_of(accounts(1)).unwrap().total.0;
+
+        //list the token again as a private sale, replacing the public one
+        testing_env!(context(nft(), 0).signer_account_id(accounts(1)).build());
+        contract.nft_on_approve(
+            "1".to_string(),
+            accounts(1),
+            1,
+            format!(
+                r#"{{"prices":{{"near":"{}"}},"reserved_for":["{}"]}}"#,
+                PRICE,
+                accounts(2)
+            ),
+        );
+        //a Vec of one AccountId in borsh: the length of the list and of the ID, then the ID
+        let reserved_for_bytes = 4 + 4 + accounts(2).as_str().len();
+        let reserved_for_cost = reserved_for_bytes as Balance * STORAGE_PRICE_PER_BYTE;
+        assert_eq!(
+            contract.storage_balance_of(accounts(1)).unwrap().total.0,
+            balance - reserved_for_cost
+        );
+
+        testing_env!(context(accounts(1), 1).build());
+        contract.remove_sale(nft(), "1".to_string());
+        assert_eq!(
+            contract.storage_balance_of(accounts(1)).unwrap().total.0,
+            balance
+        );
+    }
+
+    #[test]
+    fn token_history_is_paid_by_the_seller() {
+        let mut contract = setup();
+        purchase_with_near(&mut contract, PRICE);
+        let balance = contract.storage_balance_of(accounts(1)).unwrap().total.0;
+
+        //nft_transfer_payout went through and pays the whole price to the seller
+        let payout = format!(r#"{{"payout":{{"{}":"{}"}}}}"#, accounts(1), PRICE);
+        testing_env!(
+            context(AccountId::new_unchecked("market.near".to_string()), 0).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(payout.into_bytes())],
+        );
+        contract.resolve_purchase(
+            accounts(2),
+            U128(PRICE),
+            None,
+            U128(0),
+            SoldToken {
+                nft_contract_id: nft(),
+                token_id: "1".to_string(),
+                seller_id: accounts(1),
+            },
+        );
+
+        let last_sale = contract.get_last_sale("nft.near.1".to_string()).unwrap();
+        assert_eq!(last_sale.buyer_id, accounts(2));
+        assert!(contract.storage_balance_of(accounts(1)).unwrap().total.0 < balance);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::*;
+use std::ops::Bound;
 
 #[near_bindgen]
 impl Contract {
@@ -90,8 +91,150 @@ impl Contract {
             .collect()
     }
 
+    //returns the lowest current price of an nft contract's active public sales in a currency.
+    // Auctions aren't taken into account and dutch auctions count with their decayed price
+    pub fn get_floor_price(
+        &self,
+        nft_contract_id: AccountId,
+        ft_contract_id: FungibleTokenId,
+    ) -> Option<U128> {
+        let contract_and_ft_id = format!("{}{}{}", nft_contract_id, DELIMETER, ft_contract_id);
+        let mut floor_price: Option<u128> = None;
+
+        if let Some(by_price) = self.sales_by_price.get(&contract_and_ft_id) {
+            for ((price, token_id), _) in by_price.iter() {
+                let sale = self
+                    .sales
+                    .get(&format!("{}{}{}", nft_contract_id, DELIMETER, token_id))
+                    .unwrap();
+
+                if sale.sale_conditions.is_public() {
+                    floor_price = Some(price);
+                    break;
+                }
+            }
+        }
+
+        if let Some(decaying_sales) = self.decaying_sales.get(&contract_and_ft_id) {
+            for token_id in decaying_sales.iter() {
+                let sale = self
+                    .sales
+                    .get(&format!("{}{}{}", nft_contract_id, DELIMETER, token_id))
+                    .unwrap();
+                if !sale.sale_conditions.is_public() {
+                    continue;
+                }
+
+                if let Some(price) = sale.sale_conditions.price_for(&ft_contract_id) {
+                    floor_price = Some(floor_price.map_or(price.0, |floor| floor.min(price.0)));
+                }
+            }
+        }
+
+        floor_price.map(U128)
+    }
+
+    //returns paginated active public sales of an nft contract whose current price in a currency
+    // is between min_price and max_price (both inclusive), cheapest first. Auctions aren't
+    // included, the same sales get_floor_price looks at
+    pub fn get_sales_by_price_range(
+        &self,
+        nft_contract_id: AccountId,
+        ft_contract_id: FungibleTokenId,
+        min_price: Option<U128>,
+        max_price: Option<U128>,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Sale> {
+        let contract_and_ft_id = format!("{}{}{}", nft_contract_id, DELIMETER, ft_contract_id);
+
+        let min_price = min_price.map(|price| price.0).unwrap_or(0);
+        let max_price = max_price.map(|price| price.0).unwrap_or(u128::MAX);
+
+        let start = u128::from(from_index.unwrap_or(U128(0))) as usize;
+        let limit = limit.unwrap_or(0) as usize;
+        let sale_of = |token_id: &TokenId| {
+            self.sales
+                .get(&format!("{}{}{}", nft_contract_id, DELIMETER, token_id))
+                .unwrap()
+        };
+
+        //fixed prices come sorted from the index, so no more than the requested page is needed
+        let mut matches: Vec<(u128, Sale)> = vec![];
+        if let Some(by_price) = self.sales_by_price.get(&contract_and_ft_id) {
+            let from_key = (min_price, String::new());
+
+            matches.extend(
+                by_price
+                    .range((Bound::Included(from_key), Bound::Unbounded))
+                    .take_while(|((price, _), _)| *price <= max_price)
+                    .map(|((price, token_id), _)| (price, sale_of(&token_id)))
+                    .filter(|(_, sale)| sale.sale_conditions.is_public())
+                    .take(start.saturating_add(limit)),
+            );
+        }
+
+        //dutch auctions are priced at the current block timestamp
+        if let Some(decaying_sales) = self.decaying_sales.get(&contract_and_ft_id) {
+            for token_id in decaying_sales.iter() {
+                let sale = sale_of(&token_id);
+                if !sale.sale_conditions.is_public() {
+                    continue;
+                }
+                if let Some(price) = sale.sale_conditions.price_for(&ft_contract_id) {
+                    if (min_price..=max_price).contains(&price.0) {
+                        matches.push((price.0, sale));
+                    }
+                }
+            }
+        }
+
+        matches.sort_by(|(price, sale), (other_price, other_sale)| {
+            price
+                .cmp(other_price)
+                .then_with(|| sale.token_id.cmp(&other_sale.token_id))
+        });
+
+        matches
+            .into_iter()
+            .skip(start)
+            .take(limit)
+            .map(|(_, sale)| sale)
+            .collect()
+    }
+
     //get a sale information for a given unique sale ID (contract + DELIMITER + token ID)
     pub fn get_sale(&self, nft_contract_token: ContractAndTokenId) -> Option<Sale> {
         self.sales.get(&nft_contract_token)
     }
+
+    //get the price a buyer would pay right now for a given sale in the given currency ("near" by
+    // default), taking any price decay into account. None if the sale isn't priced in that currency
+    pub fn get_current_price(
+        &self,
+        nft_contract_token: ContractAndTokenId,
+        ft_contract_id: Option<FungibleTokenId>,
+    ) -> Option<U128> {
+        let ft_contract_id =
+            ft_contract_id.unwrap_or_else(|| AccountId::new_unchecked("near".to_string()));
+
+        self.sales
+            .get(&nft_contract_token)
+            .and_then(|sale| sale.sale_conditions.price_for(&ft_contract_id))
+    }
+
+    //returns paginated fungible tokens that sales can be priced in ("near" stands for NEAR)
+    pub fn get_approved_ft_tokens(
+        &self,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<FungibleTokenId> {
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        self.approved_ft_tokens
+            .iter()
+            .skip(start as usize)
+            .take(limit.unwrap_or(0) as usize)
+            .collect()
+    }
 }
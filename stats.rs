@@ -0,0 +1,97 @@
+use crate::*;
+
+//most storage the collection stats grow by when an account lists its first token of a
+// collection: its seller entry and, for a new collection, the stats record
+pub const STORAGE_PER_NEW_SELLER: u128 = 320 * STORAGE_PRICE_PER_BYTE;
+
+/// running totals of an nft contract's activity on the market
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionStats {
+    //cumulative price of all completed sales in each currency ("near" for NEAR)
+    pub volume: HashMap<FungibleTokenId, U128>,
+    //number of completed sales
+    pub sales_count: u64,
+    //number of distinct accounts that have listed a token of the collection
+    pub distinct_sellers: u64,
+    //number of tokens currently listed
+    pub active_listings: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+    // views
+
+    //get the volume, sale count, distinct sellers and active listings of an nft contract
+    pub fn get_collection_stats(&self, nft_contract_id: AccountId) -> CollectionStats {
+        self.collection_stats
+            .get(&nft_contract_id)
+            .unwrap_or_default()
+    }
+}
+
+impl Contract {
+    /// counts a listing of the collection, unless it replaced an existing sale of the token, and
+    /// the owner as a seller of the collection the first time they list one of its tokens. The
+    /// records stay after the sale is gone, so the owner's storage balance pays for any growth,
+    /// at most STORAGE_PER_NEW_SELLER
+    pub(crate) fn internal_record_listing(
+        &mut self,
+        nft_contract_id: &AccountId,
+        owner_id: &AccountId,
+        new_listing: bool,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        let new_seller = self
+            .collection_sellers
+            .insert(&(nft_contract_id.clone(), owner_id.clone()));
+        if !new_listing && !new_seller {
+            return;
+        }
+
+        let mut stats = self
+            .collection_stats
+            .get(nft_contract_id)
+            .unwrap_or_default();
+        if new_listing {
+            stats.active_listings += 1;
+        }
+        if new_seller {
+            stats.distinct_sellers += 1;
+        }
+        self.collection_stats.insert(nft_contract_id, &stats);
+
+        let storage_used = env::storage_usage().saturating_sub(initial_storage_usage);
+        self.internal_charge_storage(
+            owner_id,
+            Balance::from(storage_used) * STORAGE_PRICE_PER_BYTE,
+        );
+    }
+
+    /// removes a sale that was deleted, sold or not, from the active listings of its collection
+    pub(crate) fn internal_record_delisting(&mut self, nft_contract_id: &AccountId) {
+        if let Some(mut stats) = self.collection_stats.get(nft_contract_id) {
+            stats.active_listings = stats.active_listings.saturating_sub(1);
+            self.collection_stats.insert(nft_contract_id, &stats);
+        }
+    }
+
+    /// adds a completed sale to the volume and sale count of its collection
+    pub(crate) fn internal_record_sale_stats(
+        &mut self,
+        nft_contract_id: &AccountId,
+        ft_contract_id: FungibleTokenId,
+        price: U128,
+    ) {
+        let mut stats = self
+            .collection_stats
+            .get(nft_contract_id)
+            .unwrap_or_default();
+
+        let volume = stats.volume.entry(ft_contract_id).or_insert(U128(0));
+        volume.0 = volume.0.saturating_add(price.0);
+        stats.sales_count += 1;
+
+        self.collection_stats.insert(nft_contract_id, &stats);
+    }
+}
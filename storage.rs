@@ -0,0 +1,306 @@
+use crate::*;
+
+/// NEP-145 storage balance of an account
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalance {
+    pub total: U128,
+    pub available: U128,
+}
+
+/// NEP-145 bounds of the storage balance an account can hold
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalanceBounds {
+    pub min: U128,
+    pub max: Option<U128>,
+}
+
+//storage balance an account needs for its first sale, including the collection stats it adds
+const MIN_STORAGE_BALANCE: u128 = STORAGE_PER_SALE + STORAGE_PER_NEW_SELLER;
+
+/*
+    NEP-145 storage management. Every sale or offer an account has on the market
+    locks STORAGE_PER_SALE of its storage balance, anything above that is available to withdraw.
+    Listing the first token of a collection also takes the growth of its stats from the balance,
+    and a private sale takes the storage of its buyer list until the sale is removed. The trade
+    history of a sold token is paid from the seller's available balance, or the buyer's.
+*/
+
+pub trait StorageManagement {
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance;
+
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance;
+
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool;
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds;
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance>;
+}
+
+#[near_bindgen]
+impl StorageManagement for Contract {
+    //Cover the cost of storing sale objects on the contract
+    //Optional account ID is to users can pay for storage for other people.
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let storage_account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let mut deposit = env::attached_deposit();
+        let balance = self.storage_deposits.get(&storage_account_id);
+
+        if registration_only.unwrap_or(false) {
+            //registration only takes the minimum balance and refunds the rest
+            let refund = if balance.is_some() {
+                deposit
+            } else {
+                require!(
+                    deposit >= MIN_STORAGE_BALANCE,
+                    format!("Requires minimum deposit of {}", MIN_STORAGE_BALANCE)
+                );
+                deposit - MIN_STORAGE_BALANCE
+            };
+            deposit -= refund;
+
+            if refund > 0 {
+                Promise::new(env::predecessor_account_id()).transfer(refund);
+            }
+        } else if balance.is_none() {
+            require!(
+                deposit >= MIN_STORAGE_BALANCE,
+                format!("Requires minimum deposit of {}", MIN_STORAGE_BALANCE)
+            );
+        }
+
+        let balance = balance
+            .unwrap_or(0)
+            .checked_add(deposit)
+            .unwrap_or_else(|| env::panic_str("Balance Overflow"));
+        self.storage_deposits.insert(&storage_account_id, &balance);
+
+        self.internal_storage_balance(&storage_account_id, balance)
+    }
+
+    //Withdraw storage fees that aren't locked by sales or offers. Withdraws everything available
+    // when no amount is given
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+
+        let owner_id = env::predecessor_account_id();
+        let balance = self
+            .storage_deposits
+            .get(&owner_id)
+            .unwrap_or_else(|| env::panic_str("The account is not registered"));
+
+        let available = self
+            .internal_storage_balance(&owner_id, balance)
+            .available
+            .0;
+        let amount = amount.map(|amount| amount.0).unwrap_or(available);
+        require!(
+            amount <= available,
+            format!(
+                "Cannot withdraw more than the available balance of {}",
+                available
+            )
+        );
+
+        let balance = balance - amount;
+        self.storage_deposits.insert(&owner_id, &balance);
+
+        if amount > 0 {
+            Promise::new(owner_id.clone()).transfer(amount);
+        }
+
+        self.internal_storage_balance(&owner_id, balance)
+    }
+
+    //Unregister the caller and return their whole storage balance. With force, all of their
+    // sales are removed and their offers are cancelled and refunded first. Auctions that hold
+    // bids have to be settled first, even with force.
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+
+        let owner_id = env::predecessor_account_id();
+        if self.storage_deposits.get(&owner_id).is_none() {
+            return false;
+        }
+
+        if self.storage_entries_by_account(&owner_id) > 0 {
+            require!(
+                force.unwrap_or(false),
+                "Can't unregister the account with active sales or offers without force"
+            );
+            self.internal_remove_account_entries(&owner_id);
+        }
+
+        let balance = self.storage_deposits.remove(&owner_id).unwrap_or(0);
+        if balance > 0 {
+            Promise::new(owner_id).transfer(balance);
+        }
+
+        true
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: U128(MIN_STORAGE_BALANCE),
+            max: None,
+        }
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_deposits
+            .get(&account_id)
+            .map(|balance| self.internal_storage_balance(&account_id, balance))
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    //storage needed for a single sale or offer, including what listing the first token of a
+    // collection adds to its stats
+    pub fn storage_minimum_balance(&self) -> U128 {
+        U128(MIN_STORAGE_BALANCE)
+    }
+}
+
+impl Contract {
+    /// splits an account's storage balance into total and what isn't locked by sales or offers
+    pub(crate) fn internal_storage_balance(
+        &self,
+        account_id: &AccountId,
+        balance: Balance,
+    ) -> StorageBalance {
+        let locked = u128::from(self.storage_entries_by_account(account_id)) * STORAGE_PER_SALE;
+
+        StorageBalance {
+            total: U128(balance),
+            available: U128(balance.saturating_sub(locked)),
+        }
+    }
+
+    /// takes the cost of storage an account caused outside of its sales and offers from its
+    /// storage balance, panicking if the rest can't cover its sales and offers anymore
+    pub(crate) fn internal_charge_storage(&mut self, account_id: &AccountId, cost: Balance) {
+        if cost == 0 {
+            return;
+        }
+
+        let balance = self.storage_deposits.get(account_id).unwrap_or(0);
+        let locked = u128::from(self.storage_entries_by_account(account_id)) * STORAGE_PER_SALE;
+        require!(
+            balance >= locked + cost,
+            format!(
+                "Insufficient storage paid: {}, {} is locked by sales and offers and {} more is needed",
+                balance, locked, cost
+            )
+        );
+
+        self.storage_deposits.insert(account_id, &(balance - cost));
+    }
+
+    /// takes a storage cost from what an account's storage balance doesn't lock for sales and
+    /// offers. Returns false and takes nothing if that can't cover it
+    pub(crate) fn internal_try_charge_storage(
+        &mut self,
+        account_id: &AccountId,
+        cost: Balance,
+    ) -> bool {
+        let balance = match self.storage_deposits.get(account_id) {
+            Some(balance) => balance,
+            None => return cost == 0,
+        };
+        let available = self
+            .internal_storage_balance(account_id, balance)
+            .available
+            .0;
+        if available < cost {
+            return false;
+        }
+
+        self.storage_deposits.insert(account_id, &(balance - cost));
+        true
+    }
+
+    /// gives back storage taken by internal_charge_storage once it's freed, if the account is
+    /// still registered
+    pub(crate) fn internal_refund_storage(&mut self, account_id: &AccountId, amount: Balance) {
+        if amount == 0 {
+            return;
+        }
+
+        if let Some(balance) = self.storage_deposits.get(account_id) {
+            self.storage_deposits
+                .insert(account_id, &(balance + amount));
+        }
+    }
+
+    /// removes every sale and bundle of an account and cancels all of its offers, refunding escrows.
+    /// Panics if one of its auctions holds a bid, which only settling can remove
+    pub(crate) fn internal_remove_account_entries(&mut self, account_id: &AccountId) {
+        if let Some(by_owner_id) = self.by_owner_id.get(account_id) {
+            for contract_and_token_id in by_owner_id.to_vec() {
+                //drop keys of sales that are gone or were listed again by a new owner
+                let sale = match self.sales.get(&contract_and_token_id) {
+                    Some(sale) if &sale.owner_id == account_id => sale,
+                    _ => {
+                        self.internal_remove_owner_sale(account_id, &contract_and_token_id);
+                        continue;
+                    }
+                };
+                require!(
+                    !sale.has_bids(),
+                    "Can't unregister the account while one of its auctions has bids"
+                );
+                let sale = self.internal_delist_sale(
+                    AccountId::new_unchecked(sale.nft_contract_id),
+                    sale.token_id,
+                );
+
+                EventLogVariant::Delist(vec![DelistLog::from(&sale)]).emit();
+            }
+        }
+
+        if let Some(bundles) = self.bundles_by_owner_id.get(account_id) {
+            for bundle_id in bundles.to_vec() {
+                let bundle = self.internal_remove_bundle(bundle_id);
+                EventLogVariant::DelistBundle(vec![BundleLog::from(&bundle)]).emit();
+            }
+        }
+
+        if let Some(offers) = self.offers_by_buyer_id.get(account_id) {
+            for offer_id in offers.to_vec() {
+                let offer = self.internal_remove_offer(offer_id);
+                self.refund_deposit(
+                    offer.buyer_id,
+                    offer.price,
+                    ft_contract_option(offer.ft_contract_id),
+                    "Offer Refund",
+                );
+            }
+        }
+
+        if let Some(collection_offers) = self.collection_offers_by_buyer_id.get(account_id) {
+            for collection_offer_id in collection_offers.to_vec() {
+                let collection_offer = self.internal_remove_collection_offer(collection_offer_id);
+                self.refund_deposit(
+                    collection_offer.buyer_id.clone(),
+                    collection_offer.escrowed_amount(),
+                    ft_contract_option(collection_offer.ft_contract_id),
+                    "Offer Refund",
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,453 @@
+use crate::nft_callbacks::{PurchaseArgs, SaleConditionArgs};
+use crate::*;
+use near_sdk::PromiseResult;
+
+//gas resolve_swap needs besides the token and sweetener transfers, for updating the market
+// state and logging
+const GAS_FOR_RESOLVE_SWAP_BASE: Gas = Gas(15_000_000_000_000);
+
+/// the token a swap listing asks for in exchange: a specific token, or any token of the
+/// nft contract when no token ID is given
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WantedToken {
+    pub nft_contract_id: AccountId,
+    pub token_id: Option<TokenId>,
+}
+
+/// which side of a swap pays the sweetener to the other
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug,
+)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum SweetenerPayer {
+    Seller,
+    Taker,
+}
+
+/// NEAR or fungible tokens added on top of one of the tokens of a swap. The payer has to have
+/// the amount in their swap funds when the swap is accepted. Swapped tokens move through plain
+/// nft_transfer calls, so no royalties are paid on a swap, but the market keeps its protocol
+/// fee out of the sweetener
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Sweetener {
+    //token the sweetener is paid in ("near" for NEAR)
+    pub ft_contract_id: FungibleTokenId,
+    pub amount: U128,
+    pub paid_by: SweetenerPayer,
+}
+
+/// terms of a swap listing, kept on the sale in place of prices
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SwapTerms {
+    pub wanted: WantedToken,
+    pub sweetener: Option<Sweetener>,
+}
+
+//msg used to list a token as a swap
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SwapArgs {
+    pub wanted: WantedToken,
+    pub sweetener: Option<Sweetener>,
+    //optional block timestamp (in nanoseconds) after which the listing expires
+    #[serde(default)]
+    pub expires_at: Option<U64>,
+    //optional accounts the swap is reserved for, only they can accept it
+    #[serde(default)]
+    pub reserved_for: Option<Vec<AccountId>>,
+}
+
+//msg used with ft_transfer_call to add fungible tokens to the sender's swap funds
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DepositSwapFundsArgs {}
+
+//a swap whose tokens are being escrowed, passed through to resolve_swap
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingSwap {
+    pub seller_id: AccountId,
+    pub taker_id: AccountId,
+    //the listed token, going to the taker
+    pub listed_nft_contract_id: AccountId,
+    pub listed_token_id: TokenId,
+    //the wanted token, going to the seller
+    pub offered_nft_contract_id: AccountId,
+    pub offered_token_id: TokenId,
+    pub sweetener: Option<Sweetener>,
+    //part of the sweetener the market keeps, the receiver gets the rest
+    pub protocol_fee: U128,
+}
+
+impl MarketConfig {
+    /// gas to attach to resolve_swap, enough to release both tokens and pay the sweetener
+    pub(crate) fn gas_for_resolve_swap(&self) -> Gas {
+        Gas(self
+            .gas_for_nft_transfer
+            .0
+            .saturating_mul(2)
+            .saturating_add(self.gas_for_ft_transfer.0)
+            .saturating_add(GAS_FOR_RESOLVE_SWAP_BASE.0))
+    }
+
+    /// gas a swap reserves for escrowing both tokens and resolve_swap
+    pub(crate) fn gas_for_swap(&self) -> Gas {
+        Gas(self
+            .gas_for_nft_transfer
+            .0
+            .saturating_mul(2)
+            .saturating_add(self.gas_for_resolve_swap().0))
+    }
+}
+
+impl PendingSwap {
+    /// the account that pays the sweetener and the one that receives it
+    fn sweetener_parties(&self, sweetener: &Sweetener) -> (AccountId, AccountId) {
+        match sweetener.paid_by {
+            SweetenerPayer::Seller => (self.seller_id.clone(), self.taker_id.clone()),
+            SweetenerPayer::Taker => (self.taker_id.clone(), self.seller_id.clone()),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    //add the attached NEAR to the caller's swap funds, used to pay swap sweeteners. The caller
+    // has to be registered for storage
+    #[payable]
+    pub fn deposit_swap_funds(&mut self) -> U128 {
+        let near_contract_id = AccountId::new_unchecked("near".to_string());
+        self.internal_deposit_swap_funds(
+            &env::predecessor_account_id(),
+            &near_contract_id,
+            env::attached_deposit(),
+        )
+    }
+
+    //withdraw swap funds in the given token ("near" for NEAR). Withdraws everything when no
+    // amount is given
+    #[payable]
+    pub fn withdraw_swap_funds(
+        &mut self,
+        ft_contract_id: FungibleTokenId,
+        amount: Option<U128>,
+    ) -> U128 {
+        assert_one_yocto();
+
+        let account_id = env::predecessor_account_id();
+        let key = (account_id.clone(), ft_contract_id.clone());
+        let balance = self.swap_funds.get(&key).unwrap_or(0);
+        let amount = amount.map(|amount| amount.0).unwrap_or(balance);
+        require!(
+            amount <= balance,
+            format!("Cannot withdraw more than the swap funds of {}", balance)
+        );
+
+        if balance == amount {
+            self.swap_funds.remove(&key);
+        } else {
+            self.swap_funds.insert(&key, &(balance - amount));
+        }
+
+        if amount > 0 {
+            self.refund_deposit(
+                account_id,
+                U128(amount),
+                ft_contract_option(ft_contract_id),
+                "Swap Funds Withdrawal",
+            );
+        }
+
+        U128(amount)
+    }
+
+    /*
+        Check that both tokens made it into the market's escrow. If they did, hand them to
+        their new owners and pay the sweetener. Otherwise return whichever token was escrowed
+        and give the sweetener back to the payer's swap funds.
+    */
+    #[private]
+    pub fn resolve_swap(&mut self, swap: PendingSwap) -> bool {
+        let escrowed = |index| matches!(env::promise_result(index), PromiseResult::Successful(_));
+        let listed_escrowed = escrowed(0);
+        let offered_escrowed = escrowed(1);
+
+        if listed_escrowed && offered_escrowed {
+            self.release_escrowed_token(
+                swap.listed_nft_contract_id.clone(),
+                swap.listed_token_id.clone(),
+                swap.taker_id.clone(),
+                "Marketplace Swap",
+            );
+            self.release_escrowed_token(
+                swap.offered_nft_contract_id.clone(),
+                swap.offered_token_id.clone(),
+                swap.seller_id.clone(),
+                "Marketplace Swap",
+            );
+
+            if let Some(sweetener) = &swap.sweetener {
+                let (_, receiver_id) = swap.sweetener_parties(sweetener);
+                let ft_contract_id = ft_contract_option(sweetener.ft_contract_id.clone());
+                self.internal_accrue_fee(&ft_contract_id, swap.protocol_fee);
+                self.refund_deposit(
+                    receiver_id,
+                    U128(sweetener.amount.0 - swap.protocol_fee.0),
+                    ft_contract_id,
+                    "Swap Sweetener",
+                );
+            }
+
+            EventLogVariant::Swap(vec![SwapLog::from(&swap)]).emit();
+            return true;
+        }
+
+        //send back whichever token made it into escrow
+        if listed_escrowed {
+            self.release_escrowed_token(
+                swap.listed_nft_contract_id.clone(),
+                swap.listed_token_id.clone(),
+                swap.seller_id.clone(),
+                "Marketplace Swap",
+            );
+        }
+        if offered_escrowed {
+            self.release_escrowed_token(
+                swap.offered_nft_contract_id.clone(),
+                swap.offered_token_id.clone(),
+                swap.taker_id.clone(),
+                "Marketplace Swap",
+            );
+        }
+
+        if let Some(sweetener) = &swap.sweetener {
+            let (payer_id, _) = swap.sweetener_parties(sweetener);
+            self.internal_credit_swap_funds(
+                &payer_id,
+                &sweetener.ft_contract_id,
+                sweetener.amount.0,
+            );
+        }
+
+        EventLogVariant::SwapRevert(vec![SwapLog::from(&swap)]).emit();
+        false
+    }
+
+    // views
+
+    //get the swap funds an account holds in the given token ("near" for NEAR)
+    pub fn get_swap_funds(&self, account_id: AccountId, ft_contract_id: FungibleTokenId) -> U128 {
+        U128(
+            self.swap_funds
+                .get(&(account_id, ft_contract_id))
+                .unwrap_or(0),
+        )
+    }
+}
+
+impl Contract {
+    /// builds the sale conditions of a swap listing, which has no prices
+    pub(crate) fn internal_swap_listing(
+        &self,
+        swap_args: SwapArgs,
+    ) -> (SaleConditionArgs, SwapTerms) {
+        let SwapArgs {
+            wanted,
+            sweetener,
+            expires_at,
+            reserved_for,
+        } = swap_args;
+
+        if let Some(sweetener) = &sweetener {
+            require!(sweetener.amount.0 > 0, "Sweetener must be greater than 0");
+            self.assert_approved_ft_token(&sweetener.ft_contract_id);
+        }
+
+        let sale_conditions = SaleConditionArgs {
+            prices: HashMap::new(),
+            decay: None,
+            starts_at: None,
+            expires_at,
+            reserved_for,
+        };
+        sale_conditions.assert_valid_schedule();
+        sale_conditions.assert_valid_reservation();
+
+        (sale_conditions, SwapTerms { wanted, sweetener })
+    }
+
+    /// trades a token the taker approved the market for against a swap listing. Both tokens are
+    /// transferred to the market first, then resolve_swap hands them over or sends them back
+    pub(crate) fn accept_swap(
+        &mut self,
+        listing: PurchaseArgs,
+        offered_nft_contract_id: AccountId,
+        offered_token_id: TokenId,
+        taker_id: AccountId,
+        offered_approval_id: u64,
+    ) -> Promise {
+        self.assert_buying_not_paused();
+        let swap_gas = self.config.gas_for_swap();
+        require!(
+            env::prepaid_gas() - env::used_gas() >= swap_gas,
+            format!("Attached gas can't cover the {} needed to swap", swap_gas.0)
+        );
+
+        let contract_and_token_id = format!(
+            "{}{}{}",
+            listing.nft_contract_id, DELIMETER, listing.token_id
+        );
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        let terms = sale.swap.as_ref().expect("Sale is not a swap");
+
+        require!(
+            terms.wanted.nft_contract_id == offered_nft_contract_id
+                && terms
+                    .wanted
+                    .token_id
+                    .as_ref()
+                    .map(|token_id| *token_id == offered_token_id)
+                    .unwrap_or(true),
+            "Token is not wanted by the swap"
+        );
+        sale.sale_conditions.assert_active();
+        require!(sale.owner_id != taker_id, "Cannot accept your own swap");
+        require!(
+            sale.sale_conditions.is_reserved_for(&taker_id),
+            "Sale is reserved for other buyers"
+        );
+
+        let swap = PendingSwap {
+            seller_id: sale.owner_id.clone(),
+            taker_id: taker_id.clone(),
+            listed_nft_contract_id: listing.nft_contract_id.clone(),
+            listed_token_id: listing.token_id.clone(),
+            offered_nft_contract_id: offered_nft_contract_id.clone(),
+            offered_token_id: offered_token_id.clone(),
+            sweetener: terms.sweetener.clone(),
+            protocol_fee: terms
+                .sweetener
+                .as_ref()
+                .map(|sweetener| self.protocol_fee_for(sweetener.amount))
+                .unwrap_or(U128(0)),
+        };
+
+        if let Some(sweetener) = &swap.sweetener {
+            let (payer_id, _) = swap.sweetener_parties(sweetener);
+            self.internal_debit_swap_funds(
+                &payer_id,
+                &sweetener.ft_contract_id,
+                sweetener.amount.0,
+            );
+        }
+
+        self.delete_sale(listing.nft_contract_id.clone(), listing.token_id.clone());
+
+        //the offered token can't stay listed once it's traded away
+        let offered_contract_and_token_id = format!(
+            "{}{}{}",
+            offered_nft_contract_id, DELIMETER, offered_token_id
+        );
+        if let Some(offered_sale) = self.sales.get(&offered_contract_and_token_id) {
+            require!(
+                !offered_sale.has_bids(),
+                "Cannot swap an auction that has bids"
+            );
+            self.delete_sale(offered_nft_contract_id.clone(), offered_token_id.clone());
+            EventLogVariant::Delist(vec![DelistLog::from(&offered_sale)]).emit();
+        }
+
+        let memo = Some("Marketplace Swap Escrow".to_string());
+        ext_contract::ext(listing.nft_contract_id)
+            .with_attached_deposit(1)
+            .with_static_gas(self.config.gas_for_nft_transfer)
+            .nft_transfer(
+                env::current_account_id(),
+                listing.token_id,
+                Some(sale.approval_id),
+                memo.clone(),
+            )
+            .and(
+                ext_contract::ext(offered_nft_contract_id)
+                    .with_attached_deposit(1)
+                    .with_static_gas(self.config.gas_for_nft_transfer)
+                    .nft_transfer(
+                        env::current_account_id(),
+                        offered_token_id,
+                        Some(offered_approval_id),
+                        memo,
+                    ),
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(self.config.gas_for_resolve_swap())
+                    .resolve_swap(swap),
+            )
+    }
+
+    /// adds a deposit in an approved token to the swap funds of an account registered for storage,
+    /// unless buying is paused. Returns the new balance
+    pub(crate) fn internal_deposit_swap_funds(
+        &mut self,
+        account_id: &AccountId,
+        ft_contract_id: &FungibleTokenId,
+        amount: Balance,
+    ) -> U128 {
+        self.assert_buying_not_paused();
+        self.assert_approved_ft_token(ft_contract_id);
+        require!(amount > 0, "Deposit must be greater than 0");
+        //the entry stays until it's withdrawn, so it can't be made without a storage balance
+        require!(
+            self.storage_deposits.contains_key(account_id),
+            "Register a storage balance before depositing swap funds"
+        );
+
+        self.internal_credit_swap_funds(account_id, ft_contract_id, amount)
+    }
+
+    /// adds to an account's swap funds. Returns the new balance
+    pub(crate) fn internal_credit_swap_funds(
+        &mut self,
+        account_id: &AccountId,
+        ft_contract_id: &FungibleTokenId,
+        amount: Balance,
+    ) -> U128 {
+        let key = (account_id.clone(), ft_contract_id.clone());
+        let balance = self
+            .swap_funds
+            .get(&key)
+            .unwrap_or(0)
+            .checked_add(amount)
+            .unwrap_or_else(|| env::panic_str("Balance Overflow"));
+        self.swap_funds.insert(&key, &balance);
+
+        U128(balance)
+    }
+
+    /// takes from an account's swap funds, panicking if they hold less than the amount
+    fn internal_debit_swap_funds(
+        &mut self,
+        account_id: &AccountId,
+        ft_contract_id: &FungibleTokenId,
+        amount: Balance,
+    ) {
+        let key = (account_id.clone(), ft_contract_id.clone());
+        let balance = self.swap_funds.get(&key).unwrap_or(0);
+        require!(
+            balance >= amount,
+            format!(
+                "{} doesn't have enough swap funds to pay the sweetener",
+                account_id
+            )
+        );
+
+        if balance == amount {
+            self.swap_funds.remove(&key);
+        } else {
+            self.swap_funds.insert(&key, &(balance - amount));
+        }
+    }
+}